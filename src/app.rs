@@ -1,32 +1,84 @@
+use crate::bet::{Bankroll, Bet};
+use crate::client::Client;
 use crate::config::Config;
-use crate::elo::{Elo, Winner};
+use crate::elo::Winner;
+use crate::error::{Error, Result};
+use crate::rating::RatingSystem;
 use crate::state::State;
+use crate::strategy::{Context, Decision, Strategy};
 
 use crate::game;
+use crate::team;
 
+use log::{error, info, warn};
 use rusqlite::Connection;
 use tokio::sync::mpsc;
-use log::{info, error};
-use std::error::Error;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration};
 
-pub struct App {
+/// The task streaming events from SaltyBet.
+type StreamHandle = JoinHandle<Result<()>>;
+
+/// A bet we placed on the current match, waiting for the match to be decided.
+struct PendingBet {
+    /// `None` if we couldn't record the bet.
+    id: Option<i64>,
+    one: String,
+    two: String,
+    side: Winner,
+    wager: u32,
+}
+
+pub struct App<C: Client> {
     config: Config,
     db: Connection,
-    http_client: reqwest::Client,
+    client: C,
+    strategy: Box<dyn Strategy>,
+    /// Every rating system we keep up to date as fights are decided.
+    systems: Vec<Box<dyn RatingSystem>>,
+    /// The bet we placed on the current match, waiting to be settled.
+    pending_bet: Option<PendingBet>,
+    /// During a dry run, the pretend balance we bet from instead of our account.
+    bankroll: Option<Bankroll>,
+    /// Set once SaltyBet turns down our credentials. We carry on recording fights, but don't
+    /// bet on them.
+    stopped_betting: bool,
+    /// The state behind the last event we dealt with, so reconnecting doesn't have us deal
+    /// with it again.
+    last_state: game::State,
 }
 
-impl App {
-    pub fn new(config: Config, db: Connection, http_client: reqwest::Client) -> Self {
+impl<C: Client> App<C> {
+    pub fn new(
+        config: Config,
+        db: Connection,
+        client: C,
+        strategy: Box<dyn Strategy>,
+        systems: Vec<Box<dyn RatingSystem>>,
+    ) -> Self {
+        let bankroll = if config.dry_run {
+            Some(Bankroll {
+                balance: config.dry_run_balance,
+            })
+        } else {
+            None
+        };
         Self {
             config,
             db,
-            http_client,
+            client,
+            strategy,
+            systems,
+            pending_bet: None,
+            bankroll,
+            stopped_betting: false,
+            last_state: Default::default(),
         }
     }
 
     /// Wrapper around an infinite loop where we will catch an error, log it, then retry.
     #[allow(unreachable_code)]
-    pub async fn run(&mut self) -> Result<(), Box<dyn Error>> {
+    pub async fn run(&mut self) -> Result<()> {
         loop {
             if let Err(e) = self.step().await {
                 error!("Failed in the game loop: {}", e);
@@ -38,71 +90,576 @@ impl App {
     /// Performs the main loop of the program, which in ordinary circumstances should not exit.
     /// Every once in a while though, it will, so we isolate that functionality and retry at a
     /// higher level.
-    async fn step(&mut self) -> Result<(), Box<dyn Error>> {
-        // We use the client we created above in a mutable way: send off a login request
-        // to the SB website.
-        if let Err(e) = game::Game::login(&mut self.http_client, &self.config).await {
-            panic!("Could not log in! Error: {}", e);
+    async fn step(&mut self) -> Result<()> {
+        let (stream, mut inbox) = self.connect().await;
+        let mut result = Ok(());
+        while let Some((event, state)) = inbox.recv().await {
+            // If this fails we leave `last_state` alone, so the event comes round again.
+            if let Err(e) = self.handle(event).await {
+                result = Err(e);
+                break;
+            }
+            self.last_state = state;
+        }
+
+        // Otherwise the stream would carry on polling next to the one we start next time.
+        stream.abort();
+        result
+    }
+
+    /// Log in and start streaming events from SaltyBet, picking up after the last event we
+    /// dealt with.
+    async fn connect(&mut self) -> (StreamHandle, mpsc::Receiver<(game::Event, game::State)>) {
+        // Send off a login request to the site. Dry runs don't need to be logged in, but it
+        // lets us see the tier of each match.
+        if self.bankroll.is_none() {
+            if let Err(e) = self.login().await {
+                error!("Could not log in: {}", e);
+            }
+        } else if !self.config.username.is_empty() {
+            if let Err(e) = self.client.login().await {
+                warn!("Could not log in for the dry run: {}", e);
+            }
         }
 
         // Start a shared channel so we can get events from the stream.
-        let (outbox, mut inbox) = mpsc::channel(1);
-
-        // Start the stream.
-        let stream = tokio::spawn(game::Game::stream(self.config.url_state.clone(), outbox));
-        while let Some(event) = inbox.recv().await {
-            match event {
-                game::Event::Opened(ref one_name, ref two_name) => {
-                    let one = State::get_player(&self.db, &one_name);
-                    let two = State::get_player(&self.db, &two_name);
-                    let expected_winner = {
-                        if Elo::expected(&one.elo, &two.elo) >= 0.5f32 {
-                            Winner::One
-                        } else {
-                            Winner::Two
+        let (outbox, inbox) = mpsc::channel(1);
+
+        // Start the stream. It shares our cookies so it can look up the tier of each match.
+        let stream = tokio::spawn(game::Game::stream(
+            self.client.clone(),
+            self.config.poll_interval,
+            self.last_state.clone(),
+            outbox,
+        ));
+        (stream, inbox)
+    }
+
+    /// React to a single event from the stream: bet when betting opens, and update ratings and
+    /// settle our bet once the match is decided.
+    async fn handle(&mut self, event: game::Event) -> Result<()> {
+        match event {
+            game::Event::Opened(ref fight) => {
+                if self.stopped_betting {
+                    info!("Skipping {} vs {}: not logged in", fight.one, fight.two);
+                    return Ok(());
+                }
+                let one = State::get_members(&self.db, &fight.one, fight.tier)?;
+                let one = team::composite(&fight.one, fight.tier, &one, &self.systems);
+                let two = State::get_members(&self.db, &fight.two, fight.tier)?;
+                let two = team::composite(&fight.two, fight.tier, &two, &self.systems);
+                let balance = match &self.bankroll {
+                    Some(bankroll) => bankroll.balance.min(u32::MAX as u64) as u32,
+                    None => match self.balance().await {
+                        Some(balance) => balance,
+                        None => {
+                            info!("Skipping {} vs {}: not logged in", fight.one, fight.two);
+                            return Ok(());
                         }
-                    };
+                    },
+                };
+                if self.bankroll.is_some() && fight.mode == game::Mode::Tournament {
+                    // We'd need a pretend tournament balance too.
+                    info!("Skipping {} vs {}: tournament", fight.one, fight.two);
+                    return Ok(());
+                }
+                let context = Context {
+                    balance,
+                    pot: fight.pot,
+                    mode: fight.mode,
+                    head_to_head: State::get_head_to_head(&self.db, &fight.one, &fight.two)?,
+                };
 
-                    // The logic here is:
-                    // 1. try placing a bet;
-                    // 2. if no bet could be placed, login again;
-                    // 3. place bet again;
-                    // 4. if no bet could be placed, bail!
-                    if let Err(_) = game::Game::place_bet(&mut self.http_client, &expected_winner, &self.config).await
-                    {
-                        if let Ok(_) = game::Game::login(&mut self.http_client, &self.config).await {
-                            let _ =
-                                game::Game::place_bet(&mut self.http_client, &expected_winner, &self.config).await?;
-                        } else {
-                            panic!(
-                                "Cookies and credentials expired. Gotta bail to not wreak havoc on SaltyBet."
-                            );
+                let (expected_winner, wager, probability) =
+                    match self.strategy.decide(&one, &two, &context) {
+                        Decision::Bet {
+                            winner,
+                            wager,
+                            probability,
+                        } => (winner, wager, probability),
+                        Decision::Skip(reason) => {
+                            info!("Skipping {} vs {}: {}", one.name, two.name, reason);
+                            return Ok(());
                         }
+                    };
+
+                // The logic here is:
+                // 1. try placing a bet, unless this is a dry run;
+                // 2. if no bet could be placed, our session may have expired, so login again;
+                // 3. place bet again;
+                // 4. if no bet could be placed, give up on this match.
+                let mut placed: Result<()> = Ok(());
+                if self.bankroll.is_none() {
+                    placed = self.client.place_bet(&expected_winner, wager).await;
+                }
+                if placed.is_err() {
+                    placed = match self.login().await {
+                        Ok(()) => self.client.place_bet(&expected_winner, wager).await,
+                        Err(e) => Err(e),
+                    };
+                }
+
+                // Keep a record of the bet whether or not it went through.
+                let bet = Bet {
+                    side: expected_winner,
+                    wager,
+                    balance_before: balance,
+                    probability,
+                    strategy: String::from(self.strategy.name()),
+                    success: placed.is_ok(),
+                    simulated: self.bankroll.is_some(),
+                };
+                // We can still settle up without a record of the bet, so don't stop here.
+                let id = State::put_bet(&self.db, &bet)
+                    .map_err(|e| warn!("Could not save bet: {}", e))
+                    .ok();
+                self.pending_bet = Some(PendingBet {
+                    id,
+                    one: one.name.clone(),
+                    two: two.name.clone(),
+                    side: expected_winner,
+                    wager,
+                });
+                placed?;
+
+                info!(
+                    "{} a bet of {} on: {} ({})",
+                    if bet.simulated { "Simulated" } else { "Placed" },
+                    wager,
+                    match expected_winner {
+                        Winner::One => one.name.as_str(),
+                        Winner::Two => two.name.as_str(),
+                        _ => "Unknown?",
+                    },
+                    self.strategy.name()
+                );
+            }
+            game::Event::Decided(ref winner, ref fight) => {
+                // Everything about the fight is written in a single transaction, so a failure
+                // part way leaves nothing behind to be counted twice when we try again.
+                let tx = self.db.transaction()?;
+                let mut one = State::get_members(&tx, &fight.one, fight.tier)?;
+                let mut two = State::get_members(&tx, &fight.two, fight.tier)?;
+                let (one_before, two_before) = (one.clone(), two.clone());
+                // Exhibitions are often made-up teams, so we only record who fought.
+                let rated = fight.mode != game::Mode::Exhibition || self.config.rate_exhibitions;
+                if rated {
+                    team::update(&self.systems, *winner, &mut one, &mut two);
+                }
+                for player in one.iter().chain(two.iter()) {
+                    State::put_player(&tx, player)?;
+                }
+                for name in &[&fight.one, &fight.two] {
+                    if team::is_team(name) {
+                        State::put_team(&tx, name, &team::members(name))?;
                     }
+                }
+                let fight_id = State::put_event(&tx, &event)?;
+                if let (true, Some(fight_id)) = (rated, fight_id) {
+                    State::put_history(
+                        &tx,
+                        fight_id,
+                        Winner::One,
+                        &self.systems,
+                        &one_before,
+                        &one,
+                    )?;
+                    State::put_history(
+                        &tx,
+                        fight_id,
+                        Winner::Two,
+                        &self.systems,
+                        &two_before,
+                        &two,
+                    )?;
+                }
+                tx.commit()?;
+                info!(
+                    "winner: {}; one: {}; two: {}; mode: {}",
+                    winner, fight.one, fight.two, fight.mode
+                );
 
-                    info!(
-                        "Placed a bet on: {}",
-                        match expected_winner {
-                            Winner::One => one.name.as_str(),
-                            Winner::Two => two.name.as_str(),
-                            _ => "Unknown?",
+                // Settle the bet we placed on this fight, if any.
+                if let Some(pending) = self.pending_bet.take() {
+                    if pending.one == fight.one && pending.two == fight.two {
+                        let balance = match self.bankroll.as_mut() {
+                            Some(bankroll) => {
+                                bankroll.settle(pending.side, pending.wager, *winner, &fight.pot);
+                                info!("Dry run balance: {}", bankroll.balance);
+                                bankroll.balance.min(u32::MAX as u64) as u32
+                            }
+                            None => match self.balance().await {
+                                Some(balance) => balance,
+                                None => {
+                                    warn!("Could not settle our bet: not logged in");
+                                    return Ok(());
+                                }
+                            },
+                        };
+                        if let (Some(bet), Some(fight_id)) = (pending.id, fight_id) {
+                            if let Err(e) = State::settle_bet(&self.db, bet, fight_id, balance) {
+                                warn!("Could not settle bet: {}", e);
+                            }
                         }
-                    );
+                    }
                 }
-                game::Event::Decided(ref winner, ref one_name, ref two_name) => {
-                    let mut one = State::get_player(&self.db, &one_name);
-                    let mut two = State::get_player(&self.db, &two_name);
-                    Elo::update_ratings(*winner, &mut one.elo, &mut two.elo);
-                    State::put_player(&self.db, &one);
-                    State::put_player(&self.db, &two);
-                    State::put_event(&self.db, &event);
-                    info!("winner: {}; one: {}; two: {}", winner, one.name, two.name);
+            }
+            game::Event::Unrecognized(ref status) => {
+                warn!("Ignoring a match with an unrecognized status: {:?}", status);
+            }
+            game::Event::Locked(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Log in, trying again with a growing delay if that fails. Wrong credentials won't fix
+    /// themselves though, so for those we give up straight away and stop betting.
+    async fn login(&mut self) -> Result<()> {
+        let attempts = self.config.login_attempts.max(1);
+        let mut backoff = self.config.login_backoff;
+        let mut attempt = 1;
+        loop {
+            match self.client.login().await {
+                Ok(()) => return Ok(()),
+                Err(e @ Error::BadCredentials) => {
+                    error!("{} We'll stop betting until we're restarted.", e);
+                    self.stopped_betting = true;
+                    return Err(e);
                 }
-                _ => {}
+                Err(e) if attempt >= attempts => return Err(e),
+                Err(e) => {
+                    warn!("Login attempt {} of {} failed: {}", attempt, attempts, e);
+                    sleep(Duration::from_millis(backoff)).await;
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Our balance, logging in again first if our session has expired. `None` if we can't.
+    async fn balance(&mut self) -> Option<u32> {
+        match self.client.get_balance().await {
+            Ok(balance) => Some(balance),
+            Err(e) => {
+                warn!("Could not get our balance: {}", e);
+                self.login().await.ok()?;
+                self.client.get_balance().await.ok()
             }
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::SaltyBet;
+    use crate::game::{Mode, Tier};
+    use crate::mock::{self, Script};
+    use crate::rating::{self, EloSystem};
+    use crate::strategy;
+    use std::sync::{Arc, Mutex};
+
+    /// A match between "one" and "two" which player one wins.
+    fn states() -> Vec<String> {
+        let remaining = "100 more matches until the next tournament!";
+        vec![
+            mock::state("open", "one", "two", (0, 0), remaining),
+            mock::state("locked", "one", "two", (1_000, 3_000), remaining),
+            mock::state("1", "one", "two", (0, 0), remaining),
+        ]
+    }
 
-        let _ = tokio::join!(stream);
+    fn app(config: Config) -> App<SaltyBet> {
+        let http = reqwest::Client::builder()
+            .cookie_store(true)
+            .build()
+            .expect("could not build a client");
+        with_client(SaltyBet::new(http, config.clone()), config)
+    }
+
+    fn with_client<C: Client>(client: C, config: Config) -> App<C> {
+        let db = State::new(&config).expect("could not create a database");
+        let strategy = strategy::from_config(&config).expect("unknown strategy");
+        let systems = rating::all(&config);
+        App::new(config, db, client, strategy, systems)
+    }
+
+    /// Do what `App::step` does, but stop once a match has been decided.
+    async fn run_until_decided<C: Client>(app: &mut App<C>) -> Result<()> {
+        let (stream, mut inbox) = app.connect().await;
+        let mut result = Ok(());
+        while let Some((event, state)) = inbox.recv().await {
+            let decided = matches!(event, game::Event::Decided(..));
+            if let Err(e) = app.handle(event).await {
+                result = Err(e);
+                break;
+            }
+            app.last_state = state;
+            if decided {
+                break;
+            }
+        }
+        stream.abort();
+        result
+    }
+
+    /// The side, success, simulated flag and balance after of the only bet on record.
+    fn bet<C: Client>(app: &App<C>) -> (u32, bool, bool, Option<u32>) {
+        app.db
+            .query_row(
+                "SELECT side, success, simulated, balance_after FROM bets;",
+                rusqlite::NO_PARAMS,
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .expect("no bet was recorded")
+    }
+
+    #[tokio::test]
+    async fn test_app_bets_and_settles() -> Result<()> {
+        let server = mock::Server::start(Script {
+            states: states(),
+            ..Default::default()
+        })
+        .await;
+        let mut app = app(server.config());
+        run_until_decided(&mut app).await?;
+
+        // The players are tied, so we bet 420 on player one and win.
+        assert_eq!(server.seen().bets, vec!["selectedplayer=player1&wager=420"]);
+        assert_eq!(bet(&app), (1, true, false, Some(1_000)));
+
+        let fights = State::get_fights(&app.db)?;
+        assert_eq!(fights.len(), 1);
+        assert_eq!(fights[0].winner, Winner::One);
+        assert_eq!(fights[0].tier, Some(Tier::A));
+        assert_eq!(fights[0].mode, Some(Mode::Matchmaking));
+        assert_eq!(
+            fights[0].pot,
+            Some(game::Pot {
+                one: 1_000,
+                two: 3_000
+            })
+        );
+
+        let one = State::get_player(&app.db, &String::from("one"), Some(Tier::A))?;
+        assert!(one.rating(&EloSystem::default()).rating > EloSystem::default().initial().rating);
+        let history = State::get_history(&app.db, "one", "elo", 10)?;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].before, EloSystem::default().initial().rating);
+        assert_eq!(history[0].after, one.rating(&EloSystem::default()).rating);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_app_records_rejected_bet() {
+        let server = mock::Server::start(Script {
+            states: states(),
+            accept_bets: false,
+            ..Default::default()
+        })
+        .await;
+        let mut app = app(server.config());
+        assert!(run_until_decided(&mut app).await.is_err());
+
+        // We logged in again and retried before giving up.
+        assert_eq!(server.seen().logins, 2);
+        assert_eq!(server.seen().bets.len(), 2);
+        assert_eq!(bet(&app), (1, false, false, None));
+    }
+
+    #[tokio::test]
+    async fn test_app_logs_in_again_when_logged_out() -> Result<()> {
+        let server = mock::Server::start(Default::default()).await;
+        let mut app = app(server.config());
+
+        // We never logged in, which is what an expired session looks like.
+        let fight = game::Match {
+            one: String::from("one"),
+            two: String::from("two"),
+            ..Default::default()
+        };
+        app.handle(game::Event::Opened(fight)).await?;
+        assert_eq!(server.seen().logins, 1);
+        assert_eq!(server.seen().bets, vec!["selectedplayer=player1&wager=420"]);
+        assert_eq!(bet(&app), (1, true, false, None));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_app_stops_betting_with_bad_credentials() -> Result<()> {
+        let server = mock::Server::start(Script {
+            states: states(),
+            login: false,
+            ..Default::default()
+        })
+        .await;
+        let mut app = app(server.config());
+        run_until_decided(&mut app).await?;
+
+        // No point trying the same password again, but we still record the fight.
+        assert_eq!(server.seen().logins, 1);
+        assert!(server.seen().bets.is_empty());
+        assert_eq!(State::get_fights(&app.db)?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_app_dry_run() -> Result<()> {
+        let server = mock::Server::start(Script {
+            states: states(),
+            ..Default::default()
+        })
+        .await;
+        let config = Config {
+            dry_run: true,
+            dry_run_balance: 10_000,
+            ..server.config()
+        };
+        let mut app = app(config);
+        run_until_decided(&mut app).await?;
+
+        // We bet 1,000 of our pretend 10,000 at 3:1, and never told SaltyBet.
+        assert!(server.seen().bets.is_empty());
+        assert_eq!(bet(&app), (1, true, true, Some(13_000)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_app_rates_fight_once_across_reconnects() -> Result<()> {
+        let server = mock::Server::start(Script {
+            states: states(),
+            ..Default::default()
+        })
+        .await;
+        let mut app = app(server.config());
+
+        // The disk fills up just as the fight is decided, after the players were saved.
+        app.db.execute_batch(
+            "
+            CREATE TEMP TRIGGER full BEFORE INSERT ON fights
+            BEGIN
+                SELECT RAISE(ABORT, 'database or disk is full');
+            END;
+            ",
+        )?;
+        assert!(app.step().await.is_err());
+        assert!(State::get_fights(&app.db)?.is_empty());
+        assert!(State::get_history(&app.db, "one", "elo", 10)?.is_empty());
+
+        // Once there's room again, reconnecting picks the fight back up. The stream never ends,
+        // so we give up on it once it goes quiet, and then reconnect once more.
+        app.db.execute_batch("DROP TRIGGER full;")?;
+        for _ in 0..2 {
+            let quiet = tokio::time::timeout(Duration::from_millis(200), app.step()).await;
+            assert!(quiet.is_err());
+        }
+
+        assert_eq!(server.seen().bets.len(), 1);
+        assert_eq!(State::get_fights(&app.db)?.len(), 1);
+        assert_eq!(State::get_history(&app.db, "one", "elo", 10)?.len(), 1);
+        let one = State::get_player(&app.db, &String::from("one"), Some(Tier::A))?;
+        assert_eq!(one.stats.matches, 1);
+        Ok(())
+    }
+
+    /// A site which isn't SaltyBet at all, replaying match states from memory.
+    #[derive(Clone, Default)]
+    struct Replay {
+        states: Arc<Mutex<Vec<game::State>>>,
+        bets: Arc<Mutex<Vec<(Winner, u32)>>>,
+        /// How many more times logging in fails, as if the site were down.
+        failed_logins: Arc<Mutex<u32>>,
+    }
+
+    impl Client for Replay {
+        async fn login(&self) -> Result<()> {
+            let mut failed_logins = self.failed_logins.lock().expect("poisoned");
+            if *failed_logins > 0 {
+                *failed_logins -= 1;
+                return Err(Error::Parse(String::from("down for maintenance")));
+            }
+            Ok(())
+        }
+
+        async fn get_balance(&self) -> Result<u32> {
+            Ok(5_000)
+        }
+
+        async fn place_bet(&self, winner: &Winner, wager: u32) -> Result<()> {
+            self.bets.lock().expect("poisoned").push((*winner, wager));
+            Ok(())
+        }
+
+        async fn get_state(&self) -> Result<game::State> {
+            let mut states = self.states.lock().expect("poisoned");
+            if states.len() > 1 {
+                Ok(states.remove(0))
+            } else {
+                states.first().cloned().ok_or(Error::Disconnected)
+            }
+        }
+
+        async fn get_tier(&self) -> Option<Tier> {
+            None
+        }
+    }
+
+    #[tokio::test]
+    async fn test_app_with_another_client() -> Result<()> {
+        let state = |status: &str| game::State {
+            p1name: String::from("one"),
+            p2name: String::from("two"),
+            p1total: String::from("0"),
+            p2total: String::from("0"),
+            status: String::from(status),
+            remaining: String::from("100 more matches until the next tournament!"),
+            ..Default::default()
+        };
+        let client = Replay::default();
+        *client.states.lock().expect("poisoned") = vec![state("open"), state("2")];
+        let config = Config {
+            file_db: String::from("memory"),
+            poll_interval: 10,
+            strategy: String::from("favourite"),
+            rating: String::from("elo"),
+            wager: String::from("flat"),
+            tournament_wager: String::from("all-in"),
+            ..Default::default()
+        };
+        let mut app = with_client(client.clone(), config);
+        run_until_decided(&mut app).await?;
+
+        assert_eq!(
+            *client.bets.lock().expect("poisoned"),
+            vec![(Winner::One, 500)]
+        );
+        assert_eq!(bet(&app), (1, true, false, Some(5_000)));
+        assert_eq!(State::get_fights(&app.db)?[0].winner, Winner::Two);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_app_login_backs_off() {
+        let client = Replay::default();
+        *client.failed_logins.lock().expect("poisoned") = 2;
+        let config = Config {
+            file_db: String::from("memory"),
+            login_attempts: 3,
+            login_backoff: 10,
+            strategy: String::from("favourite"),
+            rating: String::from("elo"),
+            wager: String::from("flat"),
+            tournament_wager: String::from("all-in"),
+            ..Default::default()
+        };
+        let mut app = with_client(client.clone(), config);
+        assert!(app.login().await.is_ok());
+
+        // Running out of attempts isn't the same as being turned away.
+        *client.failed_logins.lock().expect("poisoned") = 3;
+        assert!(app.login().await.is_err());
+        assert!(!app.stopped_betting);
+    }
 }
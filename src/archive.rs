@@ -0,0 +1,170 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::io;
+
+use crate::elo::Winner;
+use crate::error::{Error, Result};
+use crate::fight::Fight;
+use crate::game::Pot;
+use crate::state::State;
+
+/// A fight as it's written to and read from CSV. Everything but the names is kept in the same
+/// shape as the `fights` table, so a file can be read back without losing anything.
+#[derive(Debug, Serialize, Deserialize)]
+struct Record {
+    ended: String,
+    /// 1 or 2 for whoever won, 3 for a draw.
+    winner: u32,
+    one: String,
+    two: String,
+    one_total: Option<u64>,
+    two_total: Option<u64>,
+    tier: Option<String>,
+    mode: Option<String>,
+}
+
+impl From<&Fight> for Record {
+    fn from(fight: &Fight) -> Self {
+        Record {
+            ended: fight.ended.clone(),
+            winner: fight.winner.into(),
+            one: fight.one.clone(),
+            two: fight.two.clone(),
+            one_total: fight.pot.map(|pot| pot.one),
+            two_total: fight.pot.map(|pot| pot.two),
+            tier: fight.tier.map(|tier| tier.to_string()),
+            mode: fight.mode.map(|mode| mode.to_string()),
+        }
+    }
+}
+
+impl Record {
+    fn into_fight(self) -> Result<Fight> {
+        let winner = Winner::try_from(self.winner)?;
+        let tier = match self.tier {
+            Some(tier) => Some(
+                tier.parse()
+                    .map_err(|_| Error::Parse(format!("unknown tier: {}", tier)))?,
+            ),
+            None => None,
+        };
+        let mode = match self.mode {
+            Some(mode) => Some(
+                mode.parse()
+                    .map_err(|_| Error::Parse(format!("unknown mode: {}", mode)))?,
+            ),
+            None => None,
+        };
+        Ok(Fight {
+            id: 0,
+            ended: self.ended,
+            winner,
+            one: self.one,
+            two: self.two,
+            pot: match (self.one_total, self.two_total) {
+                (Some(one), Some(two)) => Some(Pot { one, two }),
+                _ => None,
+            },
+            tier,
+            mode,
+        })
+    }
+}
+
+/// Write every fight we have on record to `writer` as CSV, oldest first. Returns how many
+/// fights were written.
+pub fn export<W: io::Write>(state: &Connection, writer: W) -> Result<usize> {
+    let fights = State::get_fights(state)?;
+    let mut writer = csv::Writer::from_writer(writer);
+    for fight in &fights {
+        writer.serialize(Record::from(fight))?;
+    }
+    writer.flush()?;
+    Ok(fights.len())
+}
+
+/// Add the fights in a CSV file written by `export`, skipping any we already have. Ratings are
+/// left alone, so they'll need recomputing afterwards. Everything is added in a single
+/// transaction, so a bad row leaves the database untouched. Returns how many fights were added.
+pub fn import<R: io::Read>(state: &mut Connection, reader: R) -> Result<usize> {
+    let tx = state.transaction()?;
+    let mut added = 0;
+    for record in csv::Reader::from_reader(reader).deserialize() {
+        let record: Record = record?;
+        if State::put_fight(&tx, &record.into_fight()?)? {
+            added += 1;
+        }
+    }
+    tx.commit()?;
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Event, Match, Mode, Tier};
+    use crate::mock;
+    use crate::player::Player;
+
+    #[test]
+    fn test_export_import_round_trip() -> Result<()> {
+        let from = mock::database()?;
+        State::put_player(&from, &Player::new(String::from("Ryu, the wanderer")))?;
+        State::put_player(&from, &Player::new(String::from("Ken")))?;
+        State::put_event(
+            &from,
+            &Event::Decided(
+                Winner::Two,
+                Match {
+                    one: String::from("Ryu, the wanderer"),
+                    two: String::from("Ken"),
+                    pot: Pot { one: 10, two: 20 },
+                    tier: Some(Tier::A),
+                    mode: Mode::Tournament,
+                },
+            ),
+        )?;
+
+        let mut csv = Vec::new();
+        assert_eq!(export(&from, &mut csv)?, 1);
+
+        let mut to = mock::database()?;
+        assert_eq!(import(&mut to, csv.as_slice())?, 1);
+        // Importing the same fights again adds nothing.
+        assert_eq!(import(&mut to, csv.as_slice())?, 0);
+
+        let strip_id = |fights: Vec<Fight>| -> Vec<Fight> {
+            fights
+                .into_iter()
+                .map(|fight| Fight { id: 0, ..fight })
+                .collect()
+        };
+        assert_eq!(
+            strip_id(State::get_fights(&to)?),
+            strip_id(State::get_fights(&from)?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_rejects_bad_rows() -> Result<()> {
+        let mut to = mock::database()?;
+        let csv = "ended,winner,one,two,one_total,two_total,tier,mode\n\
+                   2021-01-01 00:00:00,1,Ryu,Ken,,,A,matchmaking\n\
+                   2021-01-01 00:05:00,1,Ryu,Ken,,,Z,matchmaking\n";
+        assert!(matches!(
+            import(&mut to, csv.as_bytes()),
+            Err(Error::Parse(_))
+        ));
+        // Winners we don't know about aren't taken to be draws.
+        let csv = "ended,winner,one,two,one_total,two_total,tier,mode\n\
+                   2021-01-01 00:00:00,0,Ryu,Ken,,,A,matchmaking\n";
+        assert!(matches!(
+            import(&mut to, csv.as_bytes()),
+            Err(Error::Parse(_))
+        ));
+        assert!(State::get_fights(&to)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::bet::Bankroll;
+use crate::elo::Winner;
+use crate::fight::{Fight, HeadToHead};
+use crate::game::Mode;
+use crate::player::{Roster, Stats};
+use crate::rating::RatingSystem;
+use crate::strategy::{Context, Decision, Strategy};
+use crate::team;
+
+/// How a strategy fared over a single calendar month.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Month {
+    pub month: String,
+    pub bets: u32,
+    pub correct: u32,
+    pub opening_balance: u64,
+    pub closing_balance: u64,
+}
+
+/// How well a rating system predicted the fights it saw, whether or not we bet on them.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Prediction {
+    pub system: String,
+    /// Fights which weren't draws.
+    pub fights: u32,
+    /// Fights where the player the system favoured won.
+    pub correct: u32,
+    /// The sum of squared differences between the predicted chance and the outcome.
+    pub squared_error: f64,
+}
+
+impl Prediction {
+    pub fn accuracy(&self) -> f32 {
+        if self.fights == 0 {
+            0f32
+        } else {
+            self.correct as f32 / self.fights as f32
+        }
+    }
+
+    /// The Brier score of the system's predictions. Lower is better; always guessing 50/50
+    /// scores 0.25.
+    pub fn brier(&self) -> f64 {
+        if self.fights == 0 {
+            0f64
+        } else {
+            self.squared_error / self.fights as f64
+        }
+    }
+}
+
+/// The outcome of replaying recorded fights through a strategy.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Report {
+    pub strategy: String,
+    pub fights: u32,
+    pub bets: u32,
+    pub correct: u32,
+    pub skipped: u32,
+    /// Bets on fights recorded before we kept track of the pot. They count towards accuracy, but
+    /// there's no telling what they'd have paid, so they leave the balance alone.
+    pub unpriced: u32,
+    pub starting_balance: u64,
+    pub final_balance: u64,
+    /// The largest drop from a peak balance, as a fraction of that peak.
+    pub max_drawdown: f32,
+    pub months: Vec<Month>,
+    pub predictions: Vec<Prediction>,
+}
+
+impl Report {
+    /// The fraction of bets we won. Draws are refunded, so they count as neither.
+    pub fn accuracy(&self) -> f32 {
+        if self.bets == 0 {
+            0f32
+        } else {
+            self.correct as f32 / self.bets as f32
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "strategy:         {}", self.strategy)?;
+        writeln!(f, "fights:           {}", self.fights)?;
+        writeln!(f, "bets:             {}", self.bets)?;
+        writeln!(f, "skipped:          {}", self.skipped)?;
+        writeln!(f, "unpriced:         {}", self.unpriced)?;
+        writeln!(f, "accuracy:         {:.2}%", self.accuracy() * 100f32)?;
+        writeln!(f, "starting balance: {}", self.starting_balance)?;
+        writeln!(f, "final balance:    {}", self.final_balance)?;
+        writeln!(f, "max drawdown:     {:.2}%", self.max_drawdown * 100f32)?;
+        writeln!(f)?;
+        writeln!(f, "month    bets  correct  opening  closing")?;
+        for month in &self.months {
+            writeln!(
+                f,
+                "{:<8} {:>5} {:>8} {:>8} {:>8}",
+                month.month,
+                month.bets,
+                month.correct,
+                month.opening_balance,
+                month.closing_balance
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "system   accuracy   brier")?;
+        for prediction in &self.predictions {
+            writeln!(
+                f,
+                "{:<8} {:>7.2}% {:>7.4}",
+                prediction.system,
+                prediction.accuracy() * 100f32,
+                prediction.brier()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Replay `fights` in order through fresh ratings in each of the `systems`, letting `strategy`
+/// bet on each one with a simulated bankroll. We also keep track of how well each system
+/// predicted the fights, so they can be compared. Bets are paid out with the pot recorded when
+/// betting was locked. Fights recorded before we kept track of the pot are still bet on for
+/// accuracy, but leave the balance alone: paying them at even odds snowballs the bankroll.
+///
+/// Like the live bot, the strategy only gets to see an empty pot and the head-to-head record of
+/// earlier fights when deciding, and exhibitions only affect ratings if `rate_exhibitions` is
+/// set. Team members are rated as in `team::update`, and everyone's `Stats` count every fight,
+/// as in the database. We don't bet on tournament fights: they're paid from a separate balance
+/// we have no record of.
+pub fn run(
+    fights: &[Fight],
+    strategy: &dyn Strategy,
+    systems: &[Box<dyn RatingSystem>],
+    starting_balance: u64,
+    rate_exhibitions: bool,
+) -> Report {
+    let mut players = Roster::default();
+    let mut report = Report {
+        strategy: String::from(strategy.name()),
+        starting_balance,
+        predictions: systems
+            .iter()
+            .map(|system| Prediction {
+                system: String::from(system.name()),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    let mut bankroll = Bankroll {
+        balance: starting_balance,
+    };
+    let mut peak = starting_balance;
+    // Keyed by the two sides in the order they first met.
+    let mut records: HashMap<(String, String), HeadToHead> = HashMap::new();
+    // Kept apart from the roster since, unlike ratings, they don't depend on the tier.
+    let mut stats: HashMap<String, Stats> = HashMap::new();
+
+    for fight in fights {
+        let mut one_members = players.take_members(&fight.one, fight.tier);
+        let mut two_members = players.take_members(&fight.two, fight.tier);
+        for member in one_members.iter_mut().chain(two_members.iter_mut()) {
+            member.stats = stats.get(&member.name).cloned().unwrap_or_default();
+        }
+        let one = team::composite(&fight.one, fight.tier, &one_members, systems);
+        let two = team::composite(&fight.two, fight.tier, &two_members, systems);
+
+        let month = fight.ended.chars().take(7).collect::<String>();
+        if report.months.last().map(|m| &m.month) != Some(&month) {
+            report.months.push(Month {
+                month,
+                opening_balance: bankroll.balance,
+                closing_balance: bankroll.balance,
+                ..Default::default()
+            });
+        }
+
+        let mode = fight.mode.unwrap_or_default();
+        let reversed = (fight.two.clone(), fight.one.clone());
+        let head_to_head = if let Some(record) = records.get_mut(&reversed) {
+            let seen = record.reversed();
+            record.record(Winner::Two, fight.winner);
+            seen
+        } else {
+            let record = records
+                .entry((fight.one.clone(), fight.two.clone()))
+                .or_default();
+            let seen = *record;
+            record.record(Winner::One, fight.winner);
+            seen
+        };
+        let context = Context {
+            balance: bankroll.balance.min(u32::MAX as u64) as u32,
+            mode,
+            head_to_head,
+            ..Default::default()
+        };
+        report.fights += 1;
+
+        let decision = match mode {
+            Mode::Tournament => None,
+            _ => Some(strategy.decide(&one, &two, &context)),
+        };
+        match decision {
+            Some(Decision::Bet { winner, wager, .. }) if fight.winner != Winner::Draw => {
+                let current = report.months.last_mut().expect("a month was just pushed");
+                report.bets += 1;
+                current.bets += 1;
+
+                let won = match &fight.pot {
+                    Some(pot) => bankroll.settle(winner, wager, fight.winner, pot),
+                    None => {
+                        report.unpriced += 1;
+                        winner == fight.winner
+                    }
+                };
+                if won {
+                    report.correct += 1;
+                    current.correct += 1;
+                }
+                current.closing_balance = bankroll.balance;
+            }
+            Some(Decision::Skip(_)) => report.skipped += 1,
+            _ => {}
+        }
+        for (side, members) in &[(Winner::One, &one_members), (Winner::Two, &two_members)] {
+            for member in members.iter() {
+                stats.entry(member.name.clone()).or_default().record(
+                    *side,
+                    fight.winner,
+                    &fight.ended,
+                );
+            }
+        }
+
+        peak = peak.max(bankroll.balance);
+        if peak > 0 {
+            let drawdown = (peak - bankroll.balance) as f32 / peak as f32;
+            report.max_drawdown = report.max_drawdown.max(drawdown);
+        }
+
+        let unknown_team = one_members.is_empty() || two_members.is_empty();
+        if unknown_team || (mode == Mode::Exhibition && !rate_exhibitions) {
+            players.put_all(one_members);
+            players.put_all(two_members);
+            continue;
+        }
+
+        for (system, prediction) in systems.iter().zip(report.predictions.iter_mut()) {
+            let expected = system.expected(&one, &two) as f64;
+            let actual = match fight.winner {
+                Winner::One => 1f64,
+                Winner::Two => 0f64,
+                Winner::Draw => 0.5f64,
+            };
+            if fight.winner != Winner::Draw {
+                prediction.fights += 1;
+                prediction.squared_error += (expected - actual).powi(2);
+                if (expected >= 0.5f64) == (fight.winner == Winner::One) {
+                    prediction.correct += 1;
+                }
+            }
+        }
+        team::update(systems, fight.winner, &mut one_members, &mut two_members);
+        players.put_all(one_members);
+        players.put_all(two_members);
+    }
+
+    report.final_balance = bankroll.balance;
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::game::Pot;
+    use crate::player::Player;
+    use crate::rating::{self, EloSystem};
+    use crate::strategy::{Blend, Caution, Favourite, Sizing};
+    use std::cell::RefCell;
+
+    fn fight(id: i64, ended: &str, winner: Winner, pot: Option<Pot>) -> Fight {
+        Fight {
+            id,
+            ended: String::from(ended),
+            winner,
+            one: String::from("one"),
+            two: String::from("two"),
+            pot,
+            tier: None,
+            mode: None,
+        }
+    }
+
+    #[test]
+    fn test_backtest_pays_out_with_recorded_pot() {
+        let strategy = Favourite {
+            rating: Box::new(EloSystem::default()),
+            sizing: Sizing::Flat,
+            tournament: Sizing::AllIn,
+            caution: Caution::default(),
+            head_to_head: Blend::default(),
+        };
+        // The first fight is a tie in Elo, so we bet on player one and win 420 at 2:1. After
+        // that player one is the favourite, but loses a fight without a recorded pot.
+        let fights = vec![
+            fight(
+                1,
+                "2021-04-14 04:55:19",
+                Winner::One,
+                Some(Pot { one: 1, two: 2 }),
+            ),
+            fight(2, "2021-05-01 00:00:00", Winner::Two, None),
+        ];
+
+        let report = run(
+            &fights,
+            &strategy,
+            &rating::all(&Config::default()),
+            1000,
+            false,
+        );
+        assert_eq!(report.fights, 2);
+        assert_eq!(report.bets, 2);
+        assert_eq!(report.correct, 1);
+        assert_eq!(report.unpriced, 1);
+        assert_eq!(report.final_balance, 1000 + 840);
+        assert_eq!(report.months.len(), 2);
+        assert_eq!(report.months[1].month, "2021-05");
+        assert_eq!(report.months[1].opening_balance, 1840);
+        assert_eq!(report.months[1].closing_balance, 1840);
+        assert_eq!(report.max_drawdown, 0f32);
+        assert_eq!(
+            report.predictions.len(),
+            rating::all(&Config::default()).len()
+        );
+        assert_eq!(report.predictions[0].fights, 2);
+        assert_eq!(report.predictions[0].accuracy(), 0.5f32);
+        assert!(report.predictions[0].brier() > 0.25f64);
+    }
+
+    #[test]
+    fn test_backtest_draws_are_refunded() {
+        let strategy = Favourite {
+            rating: Box::new(EloSystem::default()),
+            sizing: Sizing::Flat,
+            tournament: Sizing::AllIn,
+            caution: Caution::default(),
+            head_to_head: Blend::default(),
+        };
+        let fights = vec![fight(1, "2021-04-14 04:55:19", Winner::Draw, None)];
+
+        let report = run(
+            &fights,
+            &strategy,
+            &rating::all(&Config::default()),
+            1000,
+            false,
+        );
+        assert_eq!(report.bets, 0);
+        assert_eq!(report.final_balance, 1000);
+    }
+
+    #[test]
+    fn test_backtest_is_cautious_with_newcomers() {
+        let strategy = Favourite {
+            rating: Box::new(EloSystem::default()),
+            sizing: Sizing::Flat,
+            tournament: Sizing::AllIn,
+            caution: Caution {
+                min_fights: 1,
+                ..Default::default()
+            },
+            head_to_head: Blend::default(),
+        };
+        // Exhibitions aren't rated, but they still count.
+        let mut exhibition = fight(1, "2021-04-14 04:55:19", Winner::One, None);
+        exhibition.mode = Some(Mode::Exhibition);
+        let fights = vec![
+            exhibition,
+            fight(2, "2021-04-14 05:00:00", Winner::One, None),
+        ];
+
+        let report = run(
+            &fights,
+            &strategy,
+            &rating::all(&Config::default()),
+            1000,
+            false,
+        );
+        assert_eq!((report.skipped, report.bets), (1, 1));
+    }
+
+    #[test]
+    fn test_backtest_modes() {
+        let strategy = Favourite {
+            rating: Box::new(EloSystem::default()),
+            sizing: Sizing::Flat,
+            tournament: Sizing::AllIn,
+            caution: Caution::default(),
+            head_to_head: Blend::default(),
+        };
+        let mut exhibition = fight(1, "2021-04-14 04:55:19", Winner::One, None);
+        exhibition.mode = Some(Mode::Exhibition);
+        let mut tournament = fight(2, "2021-04-14 05:00:00", Winner::One, None);
+        tournament.mode = Some(Mode::Tournament);
+        let fights = vec![exhibition, tournament];
+
+        let report = run(
+            &fights,
+            &strategy,
+            &rating::all(&Config::default()),
+            1000,
+            false,
+        );
+        assert_eq!(report.fights, 2);
+        // We bet on the exhibition, but not the tournament fight.
+        assert_eq!(report.bets, 1);
+        // Only the tournament fight was rated.
+        assert_eq!(report.predictions[0].fights, 1);
+
+        let report = run(
+            &fights,
+            &strategy,
+            &rating::all(&Config::default()),
+            1000,
+            true,
+        );
+        assert_eq!(report.predictions[0].fights, 2);
+    }
+
+    /// Skips every fight, keeping track of the head-to-head record it was shown.
+    struct Recorder(RefCell<Vec<HeadToHead>>);
+
+    impl Strategy for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn decide(&self, _one: &Player, _two: &Player, context: &Context) -> Decision {
+            self.0.borrow_mut().push(context.head_to_head);
+            Decision::Skip(String::new())
+        }
+    }
+
+    #[test]
+    fn test_backtest_head_to_head() {
+        let mut rematch = fight(2, "2021-04-14 05:00:00", Winner::One, None);
+        rematch.one = String::from("two");
+        rematch.two = String::from("one");
+        let fights = vec![
+            fight(1, "2021-04-14 04:55:19", Winner::One, None),
+            rematch,
+            fight(3, "2021-04-14 05:05:00", Winner::Draw, None),
+        ];
+
+        let strategy = Recorder(RefCell::new(Vec::new()));
+        run(
+            &fights,
+            &strategy,
+            &rating::all(&Config::default()),
+            1000,
+            false,
+        );
+        assert_eq!(
+            strategy.0.into_inner(),
+            vec![
+                HeadToHead::default(),
+                HeadToHead {
+                    wins: 0,
+                    losses: 1,
+                    draws: 0,
+                },
+                HeadToHead {
+                    wins: 1,
+                    losses: 1,
+                    draws: 0,
+                },
+            ]
+        );
+    }
+}
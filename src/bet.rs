@@ -0,0 +1,60 @@
+use crate::elo::Winner;
+use crate::game::Pot;
+
+/// SaltyBet tops up accounts that go broke, so a simulated bankroll never stays at zero.
+pub const BAILOUT: u64 = 100;
+
+/// Container for a bet we tried to place, as recorded in the `bets` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bet {
+    pub side: Winner,
+    pub wager: u32,
+    pub balance_before: u32,
+    pub probability: f32,
+    pub strategy: String,
+    pub success: bool,
+    /// Whether this was a dry run bet which never left our machine.
+    pub simulated: bool,
+}
+
+/// A pretend balance, paid out the way SaltyBet would.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bankroll {
+    pub balance: u64,
+}
+
+impl Bankroll {
+    /// Settle a `wager` on `side` once `winner` is known, paying out using the `pot` from when
+    /// betting was locked. Draws are refunded. Returns whether the bet won.
+    pub fn settle(&mut self, side: Winner, wager: u32, winner: Winner, pot: &Pot) -> bool {
+        let wager = (wager as u64).min(self.balance);
+        let won = winner != Winner::Draw && side == winner;
+        if won {
+            self.balance += pot.payout(&side, wager as u32);
+        } else if winner != Winner::Draw {
+            self.balance -= wager;
+        }
+
+        if self.balance < BAILOUT {
+            self.balance = BAILOUT;
+        }
+        won
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bankroll_settle() {
+        let pot = Pot { one: 100, two: 300 };
+        let mut bankroll = Bankroll { balance: 1000 };
+        assert!(bankroll.settle(Winner::One, 100, Winner::One, &pot));
+        assert_eq!(bankroll.balance, 1300);
+        assert!(!bankroll.settle(Winner::One, 100, Winner::Draw, &pot));
+        assert_eq!(bankroll.balance, 1300);
+        assert!(!bankroll.settle(Winner::Two, 1300, Winner::One, &pot));
+        assert_eq!(bankroll.balance, BAILOUT);
+    }
+}
@@ -0,0 +1,255 @@
+use log::{error, trace};
+use regex::Regex;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE, REFERER};
+use serde::Deserialize;
+use std::future::Future;
+
+use crate::config::Config;
+use crate::elo::Winner;
+use crate::error::{Error, Result};
+use crate::game::{State, Tier};
+
+/// Everything we need from the betting site. `SaltyBet` is the real thing; tests, or another
+/// site with the same kind of betting, can plug in their own.
+///
+/// Clones share a session, so once one of them has logged in they all have.
+pub trait Client: Clone + Send + Sync + 'static {
+    /// Log in, so the rest of our requests are made as us. Fails with `Error::BadCredentials`
+    /// if we're turned away.
+    fn login(&self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Our current balance. Fails with `Error::LoggedOut` if we need to log in again.
+    fn get_balance(&self) -> impl Future<Output = Result<u32>> + Send;
+
+    /// Bet `wager` on `winner` winning the current match.
+    fn place_bet(&self, winner: &Winner, wager: u32) -> impl Future<Output = Result<()>> + Send;
+
+    /// The state of the current match.
+    fn get_state(&self) -> impl Future<Output = Result<State>> + Send;
+
+    /// Which tier the current match is in, if we can tell.
+    fn get_tier(&self) -> impl Future<Output = Option<Tier>> + Send;
+}
+
+/// The parts of the stats SaltyBet gives Illuminati members that we care about. Everyone else
+/// gets an empty response, so every field is optional.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Stats {
+    p1tier: String,
+    p2tier: String,
+}
+
+/// Talks to SaltyBet using the URLs and credentials from the `Config`.
+#[derive(Clone)]
+pub struct SaltyBet {
+    http: reqwest::Client,
+    config: Config,
+}
+
+impl SaltyBet {
+    /// The `http` client should keep track of cookies, since that's how SaltyBet knows we've
+    /// logged in.
+    pub fn new(http: reqwest::Client, config: Config) -> Self {
+        Self { http, config }
+    }
+
+    /// Our balance as shown on the index page, or `None` if it isn't there. SaltyBet only shows
+    /// it to people who are logged in, so that's how we tell whether our session is still good.
+    async fn balance(&self) -> Result<Option<u32>> {
+        let re = Regex::new(r#"(?m)<span class="dollar" id="balance">([0-9,]+)</span>"#)?;
+        let response = self.http.get(&self.config.url_index).send().await?;
+        let body = response.text().await?;
+
+        Ok(re.captures(body.as_str()).map(|money_match| {
+            let money = &money_match[1].to_owned();
+            money.replace(",", "").parse::<u32>().unwrap_or(420u32)
+        }))
+    }
+}
+
+impl Client for SaltyBet {
+    /// Logs into SaltyBet, saving the cookies as we go along. Since we reuse the client the auth
+    /// info is available to us in subsequent requests. SaltyBet answers a bad password the same
+    /// way as a good one, so we check the index page to see whether it worked.
+    async fn login(&self) -> Result<()> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            REFERER,
+            HeaderValue::from_str(self.config.url_referer.as_str())?,
+        );
+
+        let params = [
+            ("email", self.config.username.as_str()),
+            ("pword", self.config.password.as_str()),
+            ("authenticate", "signin"),
+        ];
+
+        self.http
+            .post(&self.config.url_login)
+            .headers(headers)
+            .form(&params)
+            .send()
+            .await?;
+
+        match self.balance().await? {
+            Some(_) => Ok(()),
+            None => Err(Error::BadCredentials),
+        }
+    }
+
+    /// Gets the current balance so we know how much to bet. We'll need to be logged in. If the
+    /// balance is there but makes no sense then we return a default of `420`.
+    async fn get_balance(&self) -> Result<u32> {
+        self.balance().await?.ok_or(Error::LoggedOut)
+    }
+
+    /// Places a bet of `wager` on the `winner`. We should be logged in. If we're not, we'll log
+    /// in again at some point. It's fine for us if we don't always place a bet.
+    async fn place_bet(&self, winner: &Winner, wager: u32) -> Result<()> {
+        trace!("Betting {}", wager);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            REFERER,
+            HeaderValue::from_str(self.config.url_referer.as_str())?,
+        );
+        headers.insert(CONTENT_TYPE, HeaderValue::from_str("*/*")?);
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_str("application/x-www-form-urlencoded; charset=UTF-8")?,
+        );
+        headers.insert("X-Requested-With", HeaderValue::from_str("XMLHttpRequest")?);
+        trace!("Request headers: {:?}", headers);
+
+        let params = [
+            ("selectedplayer", String::from(winner)),
+            ("wager", wager.to_string()),
+        ];
+        trace!("Params: {:?}", params);
+
+        let response = self
+            .http
+            .post(&self.config.url_bet)
+            .headers(headers)
+            .form(&params)
+            .send()
+            .await?;
+        trace!("Response: {:?}", response);
+
+        let status = &response.status();
+        let body = response.text().await?;
+        trace!("Body: {:?}", body);
+
+        if body.ends_with('1') {
+            Ok(())
+        } else {
+            error!("Status: {}; Body: {}", status.as_u16(), body);
+            Err(Error::BetRejected)
+        }
+    }
+
+    async fn get_state(&self) -> Result<State> {
+        let response = self.http.get(&self.config.url_state).send().await?;
+        Ok(response.json::<State>().await?)
+    }
+
+    /// Looks up which tier the current match is in. SaltyBet only tells Illuminati members, so
+    /// this is `None` for everyone else, as well as for matches between tiers.
+    async fn get_tier(&self) -> Option<Tier> {
+        let stats = self
+            .http
+            .get(&self.config.url_stats)
+            .send()
+            .await
+            .ok()?
+            .json::<Stats>()
+            .await
+            .ok()?;
+        let one = stats.p1tier.parse::<Tier>().ok()?;
+        let two = stats.p2tier.parse::<Tier>().ok()?;
+        if one == two {
+            Some(one)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, Script};
+
+    fn saltybet(config: Config) -> SaltyBet {
+        let http = reqwest::Client::builder()
+            .cookie_store(true)
+            .build()
+            .expect("could not build a client");
+        SaltyBet::new(http, config)
+    }
+
+    #[tokio::test]
+    async fn test_login_and_get_balance() {
+        let server = mock::Server::start(Script {
+            balance: 1_234,
+            ..Default::default()
+        })
+        .await;
+        let client = saltybet(server.config());
+
+        // The balance is only shown to people who are logged in.
+        let error = client.get_balance().await.expect_err("we aren't logged in");
+        assert!(matches!(error, Error::LoggedOut));
+        client.login().await.expect("could not log in");
+        assert_eq!(client.get_balance().await.ok(), Some(1_234));
+        assert_eq!(server.seen().logins, 1);
+    }
+
+    #[tokio::test]
+    async fn test_login_with_bad_credentials() {
+        let server = mock::Server::start(Script {
+            login: false,
+            ..Default::default()
+        })
+        .await;
+        let client = saltybet(server.config());
+
+        let error = client.login().await.expect_err("we shouldn't be let in");
+        assert!(matches!(error, Error::BadCredentials));
+        assert_eq!(server.seen().logins, 1);
+    }
+
+    #[tokio::test]
+    async fn test_place_bet() {
+        let server = mock::Server::start(Default::default()).await;
+        let client = saltybet(server.config());
+
+        let error = client
+            .place_bet(&Winner::Two, 500)
+            .await
+            .expect_err("we aren't logged in");
+        assert!(matches!(error, Error::BetRejected));
+        client.login().await.expect("could not log in");
+        assert!(client.place_bet(&Winner::Two, 500).await.is_ok());
+        assert_eq!(
+            server.seen().bets,
+            vec!["selectedplayer=player2&wager=500"; 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_tier() {
+        let server = mock::Server::start(Script {
+            tier: String::from("S"),
+            ..Default::default()
+        })
+        .await;
+        let client = saltybet(server.config());
+
+        // Only Illuminati members get to see the tier.
+        assert_eq!(client.get_tier().await, None);
+        client.login().await.expect("could not log in");
+        assert_eq!(client.get_tier().await, Some(Tier::S));
+    }
+}
@@ -1,42 +1,495 @@
+use serde::Deserialize;
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(Default)]
+use crate::elo;
+use crate::error::{Error, Result};
+use crate::rating;
+use crate::strategy;
+
+/// Where we look for a configuration file when we haven't been pointed at one.
+pub const DEFAULT_PATH: &str = "waifu.toml";
+
+/// Everything we can be configured with. Settings come from, in increasing order of priority:
+/// the defaults below, a TOML file using the field names as keys, environment variables, and
+/// command-line flags.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub username: String,
     pub password: String,
+    /// A file holding the password, e.g. a mounted secret. Can't be used along with `password`.
+    pub password_file: Option<PathBuf>,
     pub file_db: String,
     pub url_index: String,
     pub url_login: String,
     pub url_state: String,
+    pub url_stats: String,
     pub url_bet: String,
     pub url_referer: String,
+    /// How long to wait between checks of `url_state`, in milliseconds.
+    pub poll_interval: u64,
+    /// How many times to try logging in again once our session expires.
+    pub login_attempts: u32,
+    /// How long to wait after the first failed login, in milliseconds. Doubles after each one.
+    pub login_backoff: u64,
+    pub strategy: String,
+    pub rating: String,
+    /// How far apart two Elo ratings are when the higher rated player is ten times as likely
+    /// to win.
+    pub elo_scale: f32,
+    /// How far an Elo rating can move after a fight, once a player is settled in.
+    pub elo_k_factor: f32,
+    /// The Elo K-factor for a player's first fight, which shrinks to `elo_k_factor` over their
+    /// first `elo_provisional_matches` fights.
+    pub elo_provisional_k_factor: f32,
+    pub elo_provisional_matches: u32,
+    pub wager: String,
+    pub tournament_wager: String,
+    /// Matches where either side has fewer recorded fights than this, exhibitions included, are
+    /// ones we're unsure of. A team counts as its least experienced member.
+    pub min_fights: u32,
+    /// So are matches where our chance of winning beats a coin flip by less than this.
+    pub min_edge: f32,
+    /// What to do about matches we're unsure of: "skip" them, or bet the "minimum", which is
+    /// `wager_floor`.
+    pub unsure_wager: String,
+    /// Once two sides have met at least this many times, their record against each other is
+    /// blended into our prediction, making up `head_to_head_weight` of it.
+    pub head_to_head_min: u32,
+    pub head_to_head_weight: f32,
+    pub kelly_fraction: f32,
+    pub wager_floor: u32,
+    pub wager_ceiling: Option<u32>,
+    pub backtest_balance: u64,
+    pub rate_exhibitions: bool,
+    pub dry_run: bool,
+    pub dry_run_balance: u64,
+    /// What to log, in the same format as `RUST_LOG`, which takes priority over it.
+    pub log: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            username: String::new(),
+            password: String::new(),
+            password_file: None,
+            file_db: String::from("memory"),
+            url_index: String::from("https://www.saltybet.com/"),
+            url_login: String::from("https://www.saltybet.com/authenticate?signin=1"),
+            url_state: String::from("https://www.saltybet.com/state.json"),
+            url_stats: String::from("https://www.saltybet.com/ajax_get_stats.php"),
+            url_bet: String::from("http://www.saltybet.com/ajax_place_bet.php"),
+            url_referer: String::from("http://www.saltybet.com/"),
+            poll_interval: 5_000,
+            login_attempts: 5,
+            login_backoff: 1_000,
+            strategy: String::from("favourite"),
+            rating: String::from("elo"),
+            elo_scale: elo::ALGORITHM_OF,
+            elo_k_factor: elo::K_FACTOR,
+            elo_provisional_k_factor: elo::PROVISIONAL_K_FACTOR,
+            elo_provisional_matches: elo::PROVISIONAL_MATCHES,
+            wager: String::from("flat"),
+            tournament_wager: String::from("all-in"),
+            min_fights: 0,
+            min_edge: 0f32,
+            unsure_wager: String::from("skip"),
+            head_to_head_min: 3,
+            head_to_head_weight: 0.25f32,
+            kelly_fraction: 0.5,
+            wager_floor: 420,
+            wager_ceiling: None,
+            backtest_balance: 10_000,
+            rate_exhibitions: false,
+            dry_run: false,
+            dry_run_balance: 10_000,
+            log: None,
+        }
+    }
+}
+
+impl Config {
+    /// Read the settings in the TOML file at `path`. Anything it leaves out keeps its default.
+    pub fn from_file(path: &Path) -> Result<Config> {
+        let contents = fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&contents)
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e.message())))?;
+        if !config.password.is_empty() && config.password_file.is_some() {
+            return Err(Error::Config(format!(
+                "{}: set either password or password_file, not both",
+                path.display()
+            )));
+        }
+        Ok(config)
+    }
+
+    /// Override settings with whichever of our environment variables `var` has a value for. A
+    /// password or password file from the environment replaces both of the file's.
+    pub fn apply_env<F: Fn(&str) -> Option<String>>(&mut self, var: F) -> Result<()> {
+        let text = |name: &str, field: &mut String| {
+            if let Some(value) = var(name) {
+                *field = value;
+            }
+        };
+        text("SB_USERNAME", &mut self.username);
+        text("W_FILE_PATH", &mut self.file_db);
+        text("SB_INDEX_URL", &mut self.url_index);
+        text("SB_LOGIN_URL", &mut self.url_login);
+        text("SB_STATE_URL", &mut self.url_state);
+        text("SB_STATS_URL", &mut self.url_stats);
+        text("SB_BET_URL", &mut self.url_bet);
+        text("SB_REFERER_URL", &mut self.url_referer);
+        text("W_STRATEGY", &mut self.strategy);
+        text("W_RATING", &mut self.rating);
+        text("W_WAGER", &mut self.wager);
+        text("W_TOURNAMENT_WAGER", &mut self.tournament_wager);
+        text("W_UNSURE_WAGER", &mut self.unsure_wager);
+        match (var("SB_PASSWORD"), var("SB_PASSWORD_FILE")) {
+            (Some(_), Some(_)) => {
+                return Err(Error::Config(String::from(
+                    "set either SB_PASSWORD or SB_PASSWORD_FILE, not both",
+                )))
+            }
+            (Some(password), None) => {
+                self.password = password;
+                self.password_file = None;
+            }
+            (None, Some(path)) => {
+                self.password = String::new();
+                self.password_file = Some(PathBuf::from(path));
+            }
+            (None, None) => {}
+        }
+
+        parse(&var, "SB_POLL_INTERVAL", &mut self.poll_interval)?;
+        parse(&var, "SB_LOGIN_ATTEMPTS", &mut self.login_attempts)?;
+        parse(&var, "SB_LOGIN_BACKOFF", &mut self.login_backoff)?;
+        parse(&var, "W_ELO_SCALE", &mut self.elo_scale)?;
+        parse(&var, "W_ELO_K_FACTOR", &mut self.elo_k_factor)?;
+        parse(
+            &var,
+            "W_ELO_PROVISIONAL_K_FACTOR",
+            &mut self.elo_provisional_k_factor,
+        )?;
+        parse(
+            &var,
+            "W_ELO_PROVISIONAL_MATCHES",
+            &mut self.elo_provisional_matches,
+        )?;
+        parse(&var, "W_KELLY_FRACTION", &mut self.kelly_fraction)?;
+        parse(&var, "W_WAGER_FLOOR", &mut self.wager_floor)?;
+        parse(&var, "W_MIN_FIGHTS", &mut self.min_fights)?;
+        parse(&var, "W_MIN_EDGE", &mut self.min_edge)?;
+        parse(&var, "W_HEAD_TO_HEAD_MIN", &mut self.head_to_head_min)?;
+        parse(&var, "W_HEAD_TO_HEAD_WEIGHT", &mut self.head_to_head_weight)?;
+        parse(&var, "W_BACKTEST_BALANCE", &mut self.backtest_balance)?;
+        parse(&var, "W_RATE_EXHIBITIONS", &mut self.rate_exhibitions)?;
+        parse(&var, "W_DRY_RUN", &mut self.dry_run)?;
+        parse(&var, "W_DRY_RUN_BALANCE", &mut self.dry_run_balance)?;
+        if var("W_WAGER_CEILING").is_some() {
+            let mut ceiling = 0u32;
+            parse(&var, "W_WAGER_CEILING", &mut ceiling)?;
+            self.wager_ceiling = Some(ceiling);
+        }
+        Ok(())
+    }
+
+    /// Read the password from `password_file`, if we were given one.
+    pub fn read_password_file(&mut self) -> Result<()> {
+        if let Some(path) = &self.password_file {
+            if !self.password.is_empty() {
+                return Err(Error::Config(String::from(
+                    "set either the password or a password file, not both",
+                )));
+            }
+            let password = fs::read_to_string(path)
+                .map_err(|e| Error::Config(format!("could not read {}: {}", path.display(), e)))?;
+            self.password = String::from(password.trim_end_matches(&['\r', '\n'][..]));
+        }
+        Ok(())
+    }
+
+    /// Check every setting makes sense, so we can complain now rather than halfway through a
+    /// match. Every problem we find is listed.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems: Vec<String> = Vec::new();
+
+        let urls = [
+            ("url_index", &self.url_index),
+            ("url_login", &self.url_login),
+            ("url_state", &self.url_state),
+            ("url_stats", &self.url_stats),
+            ("url_bet", &self.url_bet),
+            ("url_referer", &self.url_referer),
+        ];
+        for (name, url) in &urls {
+            match reqwest::Url::parse(url) {
+                Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => {}
+                Ok(_) => problems.push(format!("{} isn't an http(s) URL: {}", name, url)),
+                Err(e) => problems.push(format!("{} isn't a valid URL ({}): {}", name, e, url)),
+            }
+        }
+
+        if self.file_db.is_empty() {
+            problems.push(String::from(
+                "file_db should be \"memory\" or the path to a database",
+            ));
+        }
+        if self.poll_interval == 0 {
+            problems.push(String::from("poll_interval should be more than 0"));
+        }
+        if self.login_attempts == 0 {
+            problems.push(String::from("login_attempts should be more than 0"));
+        }
+        for (name, value) in &[
+            ("elo_scale", self.elo_scale),
+            ("elo_k_factor", self.elo_k_factor),
+            ("elo_provisional_k_factor", self.elo_provisional_k_factor),
+        ] {
+            if value.is_nan() || *value <= 0f32 {
+                problems.push(format!("{} should be more than 0, not {}", name, value));
+            }
+        }
+        if !(self.kelly_fraction > 0f32 && self.kelly_fraction <= 1f32) {
+            problems.push(format!(
+                "kelly_fraction should be more than 0 and at most 1, not {}",
+                self.kelly_fraction
+            ));
+        }
+        if !(self.min_edge >= 0f32 && self.min_edge < 0.5f32) {
+            problems.push(format!(
+                "min_edge should be at least 0 and less than 0.5, not {}",
+                self.min_edge
+            ));
+        }
+        if !(self.head_to_head_weight >= 0f32 && self.head_to_head_weight <= 1f32) {
+            problems.push(format!(
+                "head_to_head_weight should be between 0 and 1, not {}",
+                self.head_to_head_weight
+            ));
+        }
+        if let Some(ceiling) = self.wager_ceiling {
+            if ceiling < self.wager_floor {
+                problems.push(format!(
+                    "wager_ceiling ({}) is below wager_floor ({})",
+                    ceiling, self.wager_floor
+                ));
+            }
+        }
+
+        if rating::from_name(&self.rating, self).is_none() {
+            problems.push(format!("unknown rating: {}", self.rating));
+        }
+        for (name, wager) in &[
+            ("wager", &self.wager),
+            ("tournament_wager", &self.tournament_wager),
+        ] {
+            if strategy::sizing_from_config(wager, self).is_none() {
+                problems.push(format!("unknown {}: {}", name, wager));
+            }
+        }
+        if strategy::caution_from_config(self).is_none() {
+            problems.push(format!("unknown unsure_wager: {}", self.unsure_wager));
+        }
+        if problems.is_empty() && strategy::from_config(self).is_none() {
+            problems.push(format!("unknown strategy: {}", self.strategy));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Config(problems.join("; ")))
+        }
+    }
+}
+
+/// Override `field` with the value of the environment variable `name`, if it has one.
+fn parse<T: FromStr, F: Fn(&str) -> Option<String>>(
+    var: &F,
+    name: &str,
+    field: &mut T,
+) -> Result<()> {
+    if let Some(value) = var(name) {
+        *field = value
+            .parse()
+            .map_err(|_| Error::Config(format!("{} can't be {:?}", name, value)))?;
+    }
+    Ok(())
+}
+
+/// Put together our configuration from the file at `path` (or `W_CONFIG`, or `DEFAULT_PATH` if
+/// there's one), overridden by the environment. Command-line flags go on top, so it's up to the
+/// caller to `validate` once they have.
+pub fn configure(path: Option<&Path>) -> Result<Config> {
+    let path = path
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("W_CONFIG").map(PathBuf::from))
+        .or_else(|| Some(PathBuf::from(DEFAULT_PATH)).filter(|path| path.exists()));
+    let mut config = match path {
+        Some(path) => Config::from_file(&path)?,
+        None => Config::default(),
+    };
+    config.apply_env(|name| env::var(name).ok())?;
+    config.read_password_file()?;
+    Ok(config)
 }
 
-/// Reads variables from the environment and populates the `Config` struct.
-pub fn configure() -> Config {
-    let file_db = env::var("W_FILE_PATH").unwrap_or(String::from("memory"));
-    let url_index = env::var("SB_INDEX_URL").unwrap_or(String::from("https://www.saltybet.com/"));
-    let url_state =
-        env::var("SB_STATE_URL").unwrap_or(String::from("https://www.saltybet.com/state.json"));
-    let url_login = env::var("SB_LOGIN_URL").unwrap_or(String::from(
-        "https://www.saltybet.com/authenticate?signin=1",
-    ));
-    let url_bet = env::var("SB_BET_URL")
-        .unwrap_or(String::from("http://www.saltybet.com/ajax_place_bet.php"));
-    let url_referer =
-        env::var("SB_REFERER_URL").unwrap_or(String::from("http://www.saltybet.com/"));
-
-    let username = env::var("SB_USERNAME").expect("No SB_USERNAME environment variable supplied.");
-    let password = env::var("SB_PASSWORD").expect("No SB_PASSWORD environment variable supplied.");
-
-    Config {
-        username,
-        password,
-        file_db,
-        url_index,
-        url_login,
-        url_state,
-        url_bet,
-        url_referer,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn environment(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (String::from(*name), String::from(*value)))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_layering() -> Result<()> {
+        let mut config: Config = toml::from_str(
+            r#"
+            username = "waifu@example.com"
+            strategy = "favourite"
+            wager = "kelly"
+            poll_interval = 1000
+            "#,
+        )
+        .expect("could not parse");
+        assert_eq!(config.poll_interval, 1_000);
+        // Anything left out keeps its default.
+        assert_eq!(config.rating, "elo");
+
+        config.apply_env(environment(&[
+            ("SB_POLL_INTERVAL", "2000"),
+            ("W_WAGER_CEILING", "5000"),
+        ]))?;
+        assert_eq!(config.username, "waifu@example.com");
+        assert_eq!(config.wager, "kelly");
+        assert_eq!(config.poll_interval, 2_000);
+        assert_eq!(config.wager_ceiling, Some(5_000));
+        config.validate()
+    }
+
+    #[test]
+    fn test_example_config() {
+        let config: Config =
+            toml::from_str(include_str!("../contrib/waifu.toml")).expect("could not parse");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("pasword = \"hunter2\"").is_err());
+    }
+
+    #[test]
+    fn test_bad_environment() {
+        let mut config = Config::default();
+        let error = config
+            .apply_env(environment(&[("W_DRY_RUN", "yes")]))
+            .err()
+            .map(|e| e.to_string());
+        assert_eq!(
+            error.as_deref(),
+            Some("Invalid configuration: W_DRY_RUN can't be \"yes\"")
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Config::default().validate().is_ok());
+
+        let config = Config {
+            url_bet: String::from("saltybet.com"),
+            url_state: String::from("ftp://saltybet.com/state.json"),
+            kelly_fraction: 2f32,
+            head_to_head_weight: 1.5f32,
+            wager_floor: 1_000,
+            wager_ceiling: Some(500),
+            rating: String::from("trueskill"),
+            ..Default::default()
+        };
+        let error = config
+            .validate()
+            .expect_err("should be invalid")
+            .to_string();
+        for problem in &[
+            "url_bet isn't a valid URL",
+            "url_state isn't an http(s) URL",
+            "kelly_fraction",
+            "head_to_head_weight",
+            "wager_ceiling (500) is below wager_floor (1000)",
+            "unknown rating: trueskill",
+        ] {
+            assert!(
+                error.contains(problem),
+                "{} doesn't mention {}",
+                error,
+                problem
+            );
+        }
+    }
+
+    #[test]
+    fn test_password_file() -> Result<()> {
+        let path = env::temp_dir().join(format!("waifu-password-{}", std::process::id()));
+        fs::write(&path, "hunter2\n")?;
+        let mut config = Config {
+            password_file: Some(path.clone()),
+            ..Default::default()
+        };
+        config.read_password_file()?;
+        assert_eq!(config.password, "hunter2");
+
+        // Now there's a password as well as the file, which is ambiguous.
+        assert!(config.read_password_file().is_err());
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_password_layering() -> Result<()> {
+        let from_file = || Config {
+            password_file: Some(PathBuf::from("/run/secrets/saltybet")),
+            ..Default::default()
+        };
+
+        // The environment's password wins over the file's password file...
+        let mut config = from_file();
+        config.apply_env(environment(&[("SB_PASSWORD", "hunter2")]))?;
+        assert_eq!(config.password, "hunter2");
+        assert_eq!(config.password_file, None);
+        config.read_password_file()?;
+
+        // ...and its password file over the file's password.
+        let mut config = Config {
+            password: String::from("hunter2"),
+            ..Default::default()
+        };
+        config.apply_env(environment(&[("SB_PASSWORD_FILE", "/tmp/password")]))?;
+        assert_eq!(config.password, "");
+        assert_eq!(config.password_file, Some(PathBuf::from("/tmp/password")));
+
+        // Setting both in the same place is still ambiguous.
+        let mut config = from_file();
+        let both = environment(&[("SB_PASSWORD", "hunter2"), ("SB_PASSWORD_FILE", "/tmp/p")]);
+        assert!(config.apply_env(both).is_err());
+
+        let path = env::temp_dir().join(format!("waifu-both-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "password = \"hunter2\"\npassword_file = \"/tmp/p\"\n",
+        )?;
+        let error = Config::from_file(&path).err().map(|e| e.to_string());
+        fs::remove_file(&path)?;
+        assert!(error.expect("should be ambiguous").contains("not both"));
+        Ok(())
     }
 }
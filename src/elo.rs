@@ -1,14 +1,49 @@
-use rusqlite;
-use rusqlite::types::ToSqlOutput;
-use std::convert::From;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use std::convert::{From, TryFrom};
 use std::fmt;
 
-const ALGORITHM_OF: f32 = 400f32;
-const K_FACTOR: f32 = 32f32;
+use crate::error::Error;
+
+/// The default `Params::scale`.
+pub const ALGORITHM_OF: f32 = 400f32;
+/// The default `Params::k_factor`.
+pub const K_FACTOR: f32 = 32f32;
+/// The default `Params::provisional_k_factor`.
+pub const PROVISIONAL_K_FACTOR: f32 = 64f32;
+/// The default `Params::provisional_matches`.
+pub const PROVISIONAL_MATCHES: u32 = 20;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Elo {
     pub rating: i32,
+    /// How many fights the rating has been updated after.
+    pub matches: u32,
+}
+
+/// How ratings are turned into predictions, and how far they move after a fight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Params {
+    /// How far apart two ratings are when the higher rated player is ten times as likely to
+    /// win.
+    pub scale: f32,
+    /// The most a rating can move after a single fight, once a player is settled in.
+    pub k_factor: f32,
+    /// The K-factor for a player's very first fight. It shrinks towards `k_factor` with every
+    /// fight, so newcomers find their level quickly without veterans swinging about.
+    pub provisional_k_factor: f32,
+    /// How many fights it takes to go from `provisional_k_factor` to `k_factor`.
+    pub provisional_matches: u32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            scale: ALGORITHM_OF,
+            k_factor: K_FACTOR,
+            provisional_k_factor: PROVISIONAL_K_FACTOR,
+            provisional_matches: PROVISIONAL_MATCHES,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -28,6 +63,12 @@ impl rusqlite::ToSql for Winner {
     }
 }
 
+impl FromSql for Winner {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        Winner::try_from(u32::column_result(value)?).map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
+}
+
 impl From<&Winner> for String {
     fn from(item: &Winner) -> Self {
         match item {
@@ -38,12 +79,15 @@ impl From<&Winner> for String {
     }
 }
 
-impl From<String> for Winner {
-    fn from(item: String) -> Self {
+impl TryFrom<String> for Winner {
+    type Error = Error;
+
+    fn try_from(item: String) -> Result<Self, Self::Error> {
         match item.as_str() {
-            "player1" => Winner::One,
-            "player2" => Winner::Two,
-            _ => Winner::Draw,
+            "player1" => Ok(Winner::One),
+            "player2" => Ok(Winner::Two),
+            "" => Ok(Winner::Draw),
+            _ => Err(Error::Parse(format!("unknown winner: {}", item))),
         }
     }
 }
@@ -58,12 +102,15 @@ impl From<Winner> for u32 {
     }
 }
 
-impl From<u32> for Winner {
-    fn from(item: u32) -> Self {
+impl TryFrom<u32> for Winner {
+    type Error = Error;
+
+    fn try_from(item: u32) -> Result<Self, Self::Error> {
         match item {
-            1 => Winner::One,
-            2 => Winner::Two,
-            _ => Winner::Draw,
+            1 => Ok(Winner::One),
+            2 => Ok(Winner::Two),
+            3 => Ok(Winner::Draw),
+            _ => Err(Error::Parse(format!("unknown winner: {}", item))),
         }
     }
 }
@@ -78,27 +125,59 @@ impl fmt::Display for Winner {
     }
 }
 
+impl Default for Elo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Elo {
     /// Create a new `Elo` struct with a rating of 1000.
     pub fn new() -> Self {
-        Elo { rating: 1000 }
+        Self::with_rating(1000)
     }
 
     /// Create a new `Elo` struct with the given rating.
     pub fn with_rating(rating: i32) -> Self {
-        Self { rating }
+        Self { rating, matches: 0 }
     }
 
     /// Calculate the expected outcome of a challenge against
-    /// the `opponent`.
+    /// the `opponent`, using the default `Params`.
     ///
     /// # Example
     ///
     /// ```
-    /// player = Elo::new()
-    /// player.expected(Elo::new())
+    /// use waifu_rs::elo::Elo;
+    ///
+    /// let player = Elo::new();
+    /// assert_eq!(Elo::expected(&player, &Elo::new()), 0.5);
     /// ```
     pub fn expected(one: &Elo, two: &Elo) -> f32 {
+        Params::default().expected(one, two)
+    }
+
+    /// Update the rankings for the winner and loser, using the default `Params`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use waifu_rs::elo::{Elo, Winner};
+    ///
+    /// let mut white = Elo::with_rating(800);
+    /// let white_orig = white.clone();
+    /// let mut black = Elo::new();
+    /// Elo::update_ratings(Winner::One, &mut white, &mut black);
+    /// assert!(white.rating > white_orig.rating);
+    /// ```
+    pub fn update_ratings(winner: Winner, one: &mut Elo, two: &mut Elo) {
+        Params::default().update_ratings(winner, one, two)
+    }
+}
+
+impl Params {
+    /// Calculate the expected outcome of a challenge between `one` and `two`.
+    pub fn expected(&self, one: &Elo, two: &Elo) -> f32 {
         // Expected = Chance of winning + (Chance of drawing / 2)
         //
         // A value of 0.5 means either:
@@ -113,40 +192,40 @@ impl Elo {
         if two.rating == one.rating {
             0.5
         } else {
-            let rating: f32 = (two.rating - one.rating) as f32 / ALGORITHM_OF;
+            let rating: f32 = (two.rating - one.rating) as f32 / self.scale;
             1f32 / (1f32 + 10f32.powf(rating))
         }
     }
 
-    /// Update the rankings for the winner and loser.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// let mut white = Elo::with_rating(800);
-    /// let white_orig = white.clone();
-    /// let mut black = Elo::new();
-    /// Elo::update_ratings(&mut black, &mut white);
-    /// ```
-    pub fn update_ratings(winner: Winner, one: &mut Elo, two: &mut Elo) {
-        // The expected outcome for the two will be `1 - expected outcome for one`. This
-        // allows us to simplify some math.
-        let expected = Self::expected(&one, &two);
-
-        match winner {
-            Winner::One => {
-                one.rating = (one.rating as f32 + K_FACTOR * (1f32 - expected)).floor() as i32;
-                two.rating = (two.rating as f32 + K_FACTOR * (-1f32 + expected)).floor() as i32;
-            }
-            Winner::Two => {
-                one.rating = (one.rating as f32 + K_FACTOR * (-expected)).floor() as i32;
-                two.rating = (two.rating as f32 + K_FACTOR * expected).floor() as i32;
-            }
-            Winner::Draw => {
-                two.rating = (two.rating as f32 + K_FACTOR * 0.5f32).floor() as i32;
-                one.rating = (one.rating as f32 + K_FACTOR * 0.5f32).floor() as i32;
-            }
+    /// The K-factor for `player`'s next fight. It starts at `provisional_k_factor` and moves
+    /// in a straight line to `k_factor` over the first `provisional_matches` fights.
+    pub fn k_factor(&self, player: &Elo) -> f32 {
+        if player.matches >= self.provisional_matches {
+            return self.k_factor;
         }
+        let remaining = 1f32 - player.matches as f32 / self.provisional_matches as f32;
+        self.k_factor + (self.provisional_k_factor - self.k_factor) * remaining
+    }
+
+    /// Update the rankings for the winner and loser. A draw scores half a win for each
+    /// player, so the lower rated one gains what the other loses. Each player moves by their
+    /// own K-factor, so a newcomer beating a veteran gains more than the veteran loses; between
+    /// players with the same K-factor no rating points are made or lost.
+    pub fn update_ratings(&self, winner: Winner, one: &mut Elo, two: &mut Elo) {
+        let score = match winner {
+            Winner::One => 1f32,
+            Winner::Two => 0f32,
+            Winner::Draw => 0.5f32,
+        };
+
+        // The expected outcome for the two will be `1 - expected outcome for one`, so whatever
+        // one gains over what we expected, two loses. Rounding rather than flooring keeps the
+        // two changes the same size.
+        let surprise = score - self.expected(one, two);
+        one.rating += (self.k_factor(one) * surprise).round() as i32;
+        two.rating -= (self.k_factor(two) * surprise).round() as i32;
+        one.matches += 1;
+        two.matches += 1;
     }
 }
 
@@ -154,6 +233,17 @@ impl Elo {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_winner_conversions() {
+        for winner in &[Winner::One, Winner::Two, Winner::Draw] {
+            assert_eq!(Winner::try_from(u32::from(*winner)).ok(), Some(*winner));
+            assert_eq!(Winner::try_from(String::from(winner)).ok(), Some(*winner));
+        }
+        assert!(Winner::try_from(0u32).is_err());
+        assert!(Winner::try_from(4u32).is_err());
+        assert!(Winner::try_from(String::from("player3")).is_err());
+    }
+
     #[test]
     fn test_elo_expect_draw() {
         let one = Elo::new();
@@ -171,7 +261,7 @@ mod tests {
     #[test]
     fn test_elo_update_one_lose() {
         let mut one = Elo::with_rating(800);
-        let one_orig = one.clone();
+        let one_orig = one;
         let mut two = Elo::new();
         Elo::update_ratings(Winner::Two, &mut one, &mut two);
         Elo::update_ratings(Winner::Two, &mut one, &mut two);
@@ -200,4 +290,84 @@ mod tests {
         Elo::update_ratings(Winner::Draw, &mut one, &mut two);
         assert_eq!(two.rating, one.rating);
     }
+
+    #[test]
+    fn test_elo_draw_between_equals_changes_nothing() {
+        let mut one = Elo::new();
+        let mut two = Elo::new();
+        Elo::update_ratings(Winner::Draw, &mut one, &mut two);
+        assert_eq!((one.rating, two.rating), (1000, 1000));
+    }
+
+    #[test]
+    fn test_elo_conserves_rating() {
+        // Everyone is past their provisional fights, so they share a K-factor.
+        let params = Params {
+            provisional_matches: 0,
+            ..Default::default()
+        };
+        let mut players = [
+            Elo::with_rating(800),
+            Elo::new(),
+            Elo::with_rating(1234),
+            Elo::with_rating(1500),
+        ];
+        let total: i32 = players.iter().map(|player| player.rating).sum();
+        let outcomes = [Winner::One, Winner::Two, Winner::Draw];
+        for round in 0..30 {
+            let (i, j) = (round % 4, (round * 3 + 1) % 4);
+            if i == j {
+                continue;
+            }
+            let (mut one, mut two) = (players[i], players[j]);
+            params.update_ratings(outcomes[round % 3], &mut one, &mut two);
+            players[i] = one;
+            players[j] = two;
+            assert_eq!(
+                players.iter().map(|player| player.rating).sum::<i32>(),
+                total
+            );
+        }
+
+        // The same goes for two newcomers, who share the provisional K-factor.
+        let mut one = Elo::with_rating(900);
+        let mut two = Elo::with_rating(1100);
+        Elo::update_ratings(Winner::Draw, &mut one, &mut two);
+        assert!(one.rating > 900);
+        assert_eq!(one.rating + two.rating, 2000);
+    }
+
+    #[test]
+    fn test_elo_provisional_k_factor() {
+        let params = Params::default();
+        let mut player = Elo::new();
+        assert_eq!(params.k_factor(&player), PROVISIONAL_K_FACTOR);
+        player.matches = PROVISIONAL_MATCHES / 2;
+        assert_eq!(
+            params.k_factor(&player),
+            (PROVISIONAL_K_FACTOR + K_FACTOR) / 2f32
+        );
+        player.matches = PROVISIONAL_MATCHES + 100;
+        assert_eq!(params.k_factor(&player), K_FACTOR);
+
+        // A newcomer beating a veteran moves further than the veteran does.
+        let mut newcomer = Elo::new();
+        let mut veteran = Elo::new();
+        veteran.matches = PROVISIONAL_MATCHES;
+        params.update_ratings(Winner::One, &mut newcomer, &mut veteran);
+        assert_eq!(newcomer.rating, 1032);
+        assert_eq!(veteran.rating, 984);
+        assert_eq!((newcomer.matches, veteran.matches), (1, 21));
+    }
+
+    #[test]
+    fn test_elo_scale() {
+        let params = Params {
+            scale: 200f32,
+            ..Default::default()
+        };
+        let one = Elo::with_rating(1200);
+        let two = Elo::new();
+        assert!(params.expected(&one, &two) > Elo::expected(&one, &two));
+    }
 }
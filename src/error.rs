@@ -0,0 +1,125 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+/// Everything that can go wrong while we're talking to SaltyBet, keeping track of what happened
+/// there, moving that record in and out of files, or reading our configuration.
+#[derive(Debug)]
+pub enum Error {
+    /// We couldn't reach SaltyBet, or it answered with an error.
+    Network(reqwest::Error),
+    /// SaltyBet, or a file we were given, said something we couldn't make sense of.
+    Parse(String),
+    /// SaltyBet didn't let us in with our username and password. Trying again won't help.
+    BadCredentials,
+    /// We aren't logged in, most likely because our session expired.
+    LoggedOut,
+    /// SaltyBet didn't take our bet.
+    BetRejected,
+    /// Reading from or writing to the database failed.
+    Storage(rusqlite::Error),
+    /// A fight was between players we haven't saved, named here.
+    UnknownPlayers(Vec<String>),
+    /// Nobody is listening for events any more.
+    Disconnected,
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// Our configuration is missing something or doesn't make sense.
+    Config(String),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Network(error) => write!(f, "Could not reach SaltyBet: {}", error),
+            Error::Parse(message) => write!(f, "Could not make sense of: {}", message),
+            Error::BadCredentials => write!(f, "Bad username or password."),
+            Error::LoggedOut => write!(f, "Not logged in."),
+            Error::BetRejected => write!(f, "Could not place a bet."),
+            Error::Storage(error) => write!(f, "Database error: {}", error),
+            Error::UnknownPlayers(names) => write!(f, "Unknown players: {}", names.join(", ")),
+            Error::Disconnected => write!(f, "Nobody is listening for events."),
+            Error::Io(error) => write!(f, "Could not read or write a file: {}", error),
+            Error::Config(message) => write!(f, "Invalid configuration: {}", message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Network(error) => Some(error),
+            Error::Storage(error) => Some(error),
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    /// Responses which arrived but didn't decode are SaltyBet's fault rather than the network's.
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            Error::Parse(error.to_string())
+        } else {
+            Error::Network(error)
+        }
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for Error {
+    fn from(error: reqwest::header::InvalidHeaderValue) -> Self {
+        Error::Parse(error.to_string())
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(error: regex::Error) -> Self {
+        Error::Parse(error.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        Error::Storage(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(error: csv::Error) -> Self {
+        let message = error.to_string();
+        match error.into_kind() {
+            csv::ErrorKind::Io(error) => Error::Io(error),
+            _ => Error::Parse(message),
+        }
+    }
+}
+
+impl<T> From<tokio::sync::mpsc::error::SendError<T>> for Error {
+    fn from(_: tokio::sync::mpsc::error::SendError<T>) -> Self {
+        Error::Disconnected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_storage_error_keeps_its_source() {
+        let error = Error::from(rusqlite::Error::QueryReturnedNoRows);
+        assert!(matches!(error, Error::Storage(_)));
+        assert!(error.source().is_some());
+        assert!(Error::BetRejected.source().is_none());
+    }
+}
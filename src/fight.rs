@@ -0,0 +1,86 @@
+use crate::elo::Winner;
+use crate::game::{Mode, Pot, Tier};
+
+/// Container for a fight as recorded in the `fights` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fight {
+    pub id: i64,
+    pub ended: String,
+    pub winner: Winner,
+    pub one: String,
+    pub two: String,
+    /// The pot when betting was locked, if we were keeping track of it back then.
+    pub pot: Option<Pot>,
+    pub tier: Option<Tier>,
+    /// `None` for fights recorded before we kept track of the mode.
+    pub mode: Option<Mode>,
+}
+
+/// How two sides have done against each other, from the first side's point of view.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct HeadToHead {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl HeadToHead {
+    /// How many times the two sides have met.
+    pub fn meetings(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    /// Count a meeting the first side fought as `side`.
+    pub fn record(&mut self, side: Winner, winner: Winner) {
+        if winner == Winner::Draw {
+            self.draws += 1;
+        } else if side == winner {
+            self.wins += 1;
+        } else {
+            self.losses += 1;
+        }
+    }
+
+    /// The same record from the second side's point of view.
+    pub fn reversed(&self) -> Self {
+        HeadToHead {
+            wins: self.losses,
+            losses: self.wins,
+            draws: self.draws,
+        }
+    }
+
+    /// The first side's chance of winning the next meeting going by these alone, counting a
+    /// draw as half a win. We start from one win and one loss, so a clean sweep over a couple of
+    /// meetings doesn't look like a sure thing.
+    pub fn chance(&self) -> f32 {
+        (self.wins as f32 + self.draws as f32 / 2f32 + 1f32) / (self.meetings() as f32 + 2f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_head_to_head_chance() {
+        let mut record = HeadToHead::default();
+        assert_eq!(record.chance(), 0.5f32);
+        record.record(Winner::One, Winner::One);
+        record.record(Winner::Two, Winner::Two);
+        record.record(Winner::Two, Winner::One);
+        record.record(Winner::One, Winner::Draw);
+        assert_eq!(
+            record,
+            HeadToHead {
+                wins: 2,
+                losses: 1,
+                draws: 1,
+            }
+        );
+        assert_eq!(record.meetings(), 4);
+        assert_eq!(record.reversed().reversed(), record);
+        assert_eq!(record.reversed().losses, 2);
+        assert_eq!(record.chance(), 3.5f32 / 6f32);
+    }
+}
@@ -1,239 +1,537 @@
-use log::{error, trace};
-use regex::Regex;
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE, REFERER};
 use serde::Deserialize;
-use std::error::Error;
 use std::fmt;
+use std::str::FromStr;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::time::sleep;
 
-use crate::config::Config;
+use crate::client::Client;
 use crate::elo::Winner;
+use crate::error;
 
-#[derive(Debug)]
-struct CouldNotPlaceBetError {}
-impl Error for CouldNotPlaceBetError {}
-impl fmt::Display for CouldNotPlaceBetError {
+/// The tiers SaltyBet sorts characters into for matchmaking, strongest first. P is for
+/// potatoes, X is for the characters too broken to fight anyone else.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Tier {
+    S,
+    A,
+    B,
+    P,
+    X,
+}
+
+impl FromStr for Tier {
+    type Err = ();
+
+    fn from_str(tier: &str) -> Result<Self, Self::Err> {
+        match tier.trim() {
+            "S" => Ok(Tier::S),
+            "A" => Ok(Tier::A),
+            "B" => Ok(Tier::B),
+            "P" => Ok(Tier::P),
+            "X" => Ok(Tier::X),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tier = match self {
+            Tier::S => "S",
+            Tier::A => "A",
+            Tier::B => "B",
+            Tier::P => "P",
+            Tier::X => "X",
+        };
+        write!(f, "{}", tier)
+    }
+}
+
+/// What kind of match is being fought. SaltyBet cycles through matchmaking, then a tournament,
+/// then a handful of exhibitions, and back to matchmaking.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    /// Regular matches between characters of the same tier.
+    #[default]
+    Matchmaking,
+    /// Bracket matches. Everyone bets from a separate balance which resets every tournament.
+    Tournament,
+    /// Matches requested by viewers, often between made-up teams.
+    Exhibition,
+}
+
+impl Mode {
+    /// Figure out the mode from the `remaining` and `alert` strings in the state payload, e.g.
+    /// "82 more matches until the next tournament!" or "5 exhibition matches left!". The
+    /// strings describe what's coming next, so the last match of each mode announces the next
+    /// one. Anything we don't recognise is taken to be matchmaking.
+    fn parse(remaining: &str, alert: &str) -> Mode {
+        for text in &[remaining, alert] {
+            let text = text.to_lowercase();
+            if text.contains("until the next tournament")
+                || text.starts_with("tournament mode will be activated")
+            {
+                return Mode::Matchmaking;
+            } else if text.contains("in the bracket")
+                || text.starts_with("final round")
+                || text.starts_with("tournament mode start")
+            {
+                return Mode::Tournament;
+            } else if text.contains("exhibition match") || text.starts_with("exhibition mode start")
+            {
+                return Mode::Exhibition;
+            }
+        }
+        Mode::Matchmaking
+    }
+}
+
+impl FromStr for Mode {
+    type Err = ();
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "matchmaking" => Ok(Mode::Matchmaking),
+            "tournament" => Ok(Mode::Tournament),
+            "exhibition" => Ok(Mode::Exhibition),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Could not place a bet.")
+        let mode = match self {
+            Mode::Matchmaking => "matchmaking",
+            Mode::Tournament => "tournament",
+            Mode::Exhibition => "exhibition",
+        };
+        write!(f, "{}", mode)
     }
 }
 
+/// Who is fighting in a match, and what we know about it. Teams fight under a single name; see
+/// `team::members` for who's in them.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Match {
+    pub one: String,
+    pub two: String,
+    pub pot: Pot,
+    /// `None` if we couldn't find out which tier the match is in.
+    pub tier: Option<Tier>,
+    pub mode: Mode,
+}
+
 /// The different events a match can emit.
-/// N.B. this does not take into account team fights.
-#[allow(dead_code)]
 #[derive(Debug, PartialEq)]
 pub enum Event {
-    Unknown,
-    Opened(String, String),
-    Locked,
-    Decided(Winner, String, String),
+    /// The match reached a status we don't know what to make of, which is kept here. Nothing
+    /// is recorded for it.
+    Unrecognized(String),
+    Opened(Match),
+    Locked(Pot),
+    Decided(Winner, Match),
 }
 
 /// States of a match. Not all fields are used, some are specified solely to
 /// make serde/reqwest happy when deserializing the response from the server.S
 ///
 /// Side-note: this format of the response hasn't changed in ~3 years.
-/// N.B. this does not take into account team fights.
-#[derive(Deserialize, Default, PartialEq, Clone)]
-struct State {
-    p1name: String,
-    p2name: String,
-    p1total: String,
-    p2total: String,
-    status: String,
-    alert: String,
-    x: u8,
-    remaining: String,
+#[derive(Deserialize, Default, Debug, PartialEq, Clone)]
+pub struct State {
+    pub p1name: String,
+    pub p2name: String,
+    pub p1total: String,
+    pub p2total: String,
+    pub status: String,
+    pub alert: String,
+    pub x: u8,
+    pub remaining: String,
 }
 
-pub struct Game {}
-
-impl Game {
-    /// Logs into SaltyBet, saving the cookies as we go along. Since we reuse the client the auth
-    /// info is available to us in subsequent requests.
-    pub async fn login(
-        client: &mut reqwest::Client,
-        config: &Config,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            REFERER,
-            HeaderValue::from_str(&config.url_referer.as_str()).unwrap(),
-        );
-
-        let params = [
-            ("email", config.username.as_str()),
-            ("pword", config.password.as_str()),
-            ("authenticate", "signin"),
-        ];
-
-        client
-            .post(&config.url_login)
-            .headers(headers)
-            .form(&params)
-            .send()
-            .await?;
-
-        Ok(())
-    }
-
-    /// Uses the `client` to place a bet. We should be logged in. If we're not, we'll log in again
-    /// at some point. It's fine for us if we don't always place a bet.
-    pub async fn place_bet(
-        client: &mut reqwest::Client,
-        winner: &Winner,
-        config: &Config,
-    ) -> Result<(), Box<dyn Error>> {
-        let wager = match Game::get_balance(client, config).await {
-            Ok(m) => {
-                if m >= 420_0 {
-                    m / 10
-                } else {
-                    420u32
-                }
-            }
-            _ => 420u32,
-        };
-        trace!("Betting {}", wager);
+/// How much money has been bet on each side of a match.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Pot {
+    pub one: u64,
+    pub two: u64,
+}
 
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            REFERER,
-            HeaderValue::from_str(&config.url_referer.as_str())?,
-        );
-        headers.insert(CONTENT_TYPE, HeaderValue::from_str("*/*")?);
-        headers.insert(
-            ACCEPT,
-            HeaderValue::from_str("application/x-www-form-urlencoded; charset=UTF-8")?,
-        );
-        headers.insert("X-Requested-With", HeaderValue::from_str("XMLHttpRequest")?);
-        trace!("Request headers: {:?}", headers);
+impl Pot {
+    /// The net odds we'd be paid for betting on `winner`, i.e. how much we win for every dollar
+    /// wagered. `None` if either side of the pot is still empty.
+    pub fn odds(&self, winner: &Winner) -> Option<f32> {
+        if self.one == 0 || self.two == 0 {
+            return None;
+        }
 
-        let params = [
-            ("selectedplayer", String::from(winner)),
-            ("wager", wager.to_string()),
-        ];
-        trace!("Params: {:?}", params);
-
-        let response = client
-            .post(&config.url_bet)
-            .headers(headers)
-            .form(&params)
-            .send()
-            .await?;
-        trace!("Response: {:?}", response);
-
-        let status = &response.status();
-        let body = response.text().await?;
-        trace!("Body: {:?}", body);
-
-        if body.ends_with("1") {
-            Ok(())
-        } else {
-            error!("Status: {}; Body: {}", status.as_u16(), body);
-            Err(Box::new(CouldNotPlaceBetError {}))
+        match winner {
+            Winner::One => Some(self.two as f32 / self.one as f32),
+            Winner::Two => Some(self.one as f32 / self.two as f32),
+            Winner::Draw => None,
         }
     }
 
-    /// Gets the current balance so we know how much to bet. We'll need to be logged in. If
-    /// anything goes wrong then we return a default of `420`.
-    async fn get_balance(
-        client: &mut reqwest::Client,
-        config: &Config,
-    ) -> Result<u32, Box<dyn Error + Send + Sync>> {
-        let re = Regex::new(r#"(?m)<span class="dollar" id="balance">([0-9,]+)</span>"#)?;
-        let response = client.get(&config.url_index).send().await?;
-        let body = response.text().await?;
-
-        if let Some(money_match) = re.captures(body.as_str()) {
-            let money = &money_match[1].to_owned();
-            money.replace(",", "").parse::<u32>().or(Ok(420u32))
-        } else {
-            Ok(420)
+    /// How much a `wager` on `side` wins if `side` wins the match, not counting the wager
+    /// itself. SaltyBet rounds winnings up; with an empty pot we assume even odds.
+    pub fn payout(&self, side: &Winner, wager: u32) -> u64 {
+        let odds = self.odds(side).unwrap_or(1f32);
+        (wager as f64 * odds as f64).ceil() as u64
+    }
+}
+
+impl From<&State> for Pot {
+    fn from(item: &State) -> Self {
+        let parse = |total: &String| total.replace(",", "").parse::<u64>().unwrap_or(0);
+        Pot {
+            one: parse(&item.p1total),
+            two: parse(&item.p2total),
         }
     }
+}
+
+pub struct Game {}
 
-    /// Sends an `Event` to the `outbox` specified. We only send an event when the
-    /// current match state has changed.
-    pub async fn stream(
-        url: String,
-        outbox: mpsc::Sender<Event>,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let mut current_state: State = Default::default();
+impl Game {
+    /// Sends an `Event` to the `outbox` specified, along with the state it came from. We only
+    /// send an event when the match state has changed since `current_state`, which we check for
+    /// every `interval` milliseconds. The `client` shares a session with the caller, so once
+    /// they've logged in we can look up the tier of each match as betting opens.
+    ///
+    /// Picking up from the last state the caller dealt with means restarting the stream doesn't
+    /// report the same event twice.
+    pub async fn stream<C: Client>(
+        client: C,
+        interval: u64,
+        mut current_state: State,
+        outbox: mpsc::Sender<(Event, State)>,
+    ) -> error::Result<()> {
+        // We may be picking up in the middle of a match, after betting opened.
+        let mut tier = match current_state.status.as_str() {
+            "open" | "locked" => client.get_tier().await,
+            _ => None,
+        };
         loop {
-            sleep(Duration::from_secs(5)).await;
-            if let Ok(r) = reqwest::get(&url).await {
-                if let Ok(state) = r.json::<State>().await {
-                    if current_state == state {
-                        continue;
-                    }
-                    Self::process_stream_event(state.clone(), &outbox).await?;
-                    current_state = state;
+            sleep(Duration::from_millis(interval)).await;
+            if let Ok(state) = client.get_state().await {
+                if current_state == state {
+                    continue;
+                }
+                if state.status == "open" {
+                    tier = client.get_tier().await;
                 }
+                let event = Self::process_stream_event(state.clone(), &current_state, tier);
+                outbox.send((event, state.clone())).await?;
+                current_state = state;
             }
         }
     }
 
-    /// Figure out which event to send to the `outbox`.
+    /// Figure out which event `state` amounts to, coming after `previous`.
     /// The values for this are:
     /// - locked: betting has been locked;
     /// - open: you can place your bets for either p1 or p2;
     /// - 1: player 1 has won;
-    /// - 2: player 2 has won.
+    /// - 2: player 2 has won;
+    /// - draw: neither player has won.
     ///
-    /// N.B. this does not take into account team fights.
-    async fn process_stream_event(
-        state: State,
-        outbox: &mpsc::Sender<Event>,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// Any other status is passed along as `Event::Unrecognized` rather than guessed at.
+    ///
+    /// Once a match is decided we attach the pot from when betting was locked, falling back to
+    /// the totals in the current state if we never saw the lock. Likewise the mode is taken from
+    /// the state betting opened with, since by the time a match is decided `remaining` may
+    /// already be counting down to the next mode.
+    fn process_stream_event(state: State, previous: &State, tier: Option<Tier>) -> Event {
         match state.status.as_str() {
-            "locked" => {
-                outbox.send(Event::Locked).await?;
-            }
+            "locked" => Event::Locked(Pot::from(&state)),
             "open" => {
-                outbox
-                    .send(Event::Opened(state.p1name, state.p2name))
-                    .await?;
+                let pot = Pot::from(&state);
+                let mode = Mode::parse(&state.remaining, &state.alert);
+                Event::Opened(Match {
+                    one: state.p1name,
+                    two: state.p2name,
+                    pot,
+                    tier,
+                    mode,
+                })
             }
-            outcome => {
-                let winner = match outcome {
+            "1" | "2" | "draw" => {
+                let winner = match state.status.as_str() {
                     "1" => Winner::One,
                     "2" => Winner::Two,
                     _ => Winner::Draw,
                 };
-                outbox
-                    .send(Event::Decided(winner, state.p1name, state.p2name))
-                    .await?
+                let pot = if previous.status == "locked" {
+                    Pot::from(previous)
+                } else {
+                    Pot::from(&state)
+                };
+                let mode = if previous.status == "locked" || previous.status == "open" {
+                    Mode::parse(&previous.remaining, &previous.alert)
+                } else {
+                    Mode::parse(&state.remaining, &state.alert)
+                };
+                Event::Decided(
+                    winner,
+                    Match {
+                        one: state.p1name,
+                        two: state.p2name,
+                        pot,
+                        tier,
+                        mode,
+                    },
+                )
             }
+            _ => Event::Unrecognized(state.status),
         }
-
-        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::client::SaltyBet;
+    use crate::mock::{self, Script};
     use tokio::sync::mpsc;
 
-    #[tokio::test]
-    async fn test_process_stream_event_player_one_wins() {
-        let mut state: State = Default::default();
-        state.status = String::from("1");
-        state.p1name = String::from("winner");
-        state.p1name = String::from("loser");
+    #[test]
+    fn test_process_stream_event_player_one_wins() {
+        let state = State {
+            status: String::from("1"),
+            p1name: String::from("winner"),
+            p2name: String::from("loser"),
+            ..Default::default()
+        };
 
-        let (outbox, mut inbox) = mpsc::channel(1);
-        let _ = Game::process_stream_event(state.clone(), &outbox).await;
-        let response = inbox.recv().await;
-        assert!(response.is_some());
+        let message = Game::process_stream_event(state.clone(), &Default::default(), None);
+        assert_eq!(
+            message,
+            Event::Decided(
+                Winner::One,
+                Match {
+                    one: state.p1name,
+                    two: state.p2name,
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_process_stream_event_draw() {
+        let state = State {
+            status: String::from("draw"),
+            ..Default::default()
+        };
 
-        let message = response.expect("failed");
+        assert!(matches!(
+            Game::process_stream_event(state, &Default::default(), None),
+            Event::Decided(Winner::Draw, _)
+        ));
+    }
+
+    #[test]
+    fn test_process_stream_event_unrecognized() {
+        for status in &["", "3", "nonsense"] {
+            let state = State {
+                status: String::from(*status),
+                ..Default::default()
+            };
+            assert_eq!(
+                Game::process_stream_event(state, &Default::default(), None),
+                Event::Unrecognized(String::from(*status))
+            );
+        }
+    }
+
+    #[test]
+    fn test_process_stream_event_carries_locked_pot() {
+        let locked = State {
+            status: String::from("locked"),
+            p1name: String::from("one"),
+            p2name: String::from("two"),
+            p1total: String::from("1200"),
+            p2total: String::from("3400"),
+            ..Default::default()
+        };
+        let decided = State {
+            status: String::from("2"),
+            p1total: String::from("0"),
+            p2total: String::from("0"),
+            ..locked.clone()
+        };
+
+        let message = Game::process_stream_event(decided, &locked, Some(Tier::B));
         assert_eq!(
             message,
-            Event::Decided(Winner::One, state.p1name, state.p2name)
+            Event::Decided(
+                Winner::Two,
+                Match {
+                    one: String::from("one"),
+                    two: String::from("two"),
+                    pot: Pot {
+                        one: 1200,
+                        two: 3400
+                    },
+                    tier: Some(Tier::B),
+                    mode: Mode::Matchmaking,
+                }
+            )
         );
     }
+
+    #[test]
+    fn test_pot_odds() {
+        let state = State {
+            p1total: String::from("1,000"),
+            p2total: String::from("3000"),
+            ..Default::default()
+        };
+        let pot = Pot::from(&state);
+        assert_eq!(
+            pot,
+            Pot {
+                one: 1000,
+                two: 3000
+            }
+        );
+        assert_eq!(pot.odds(&Winner::One), Some(3f32));
+        assert_eq!(Pot::default().odds(&Winner::One), None);
+        assert_eq!(pot.payout(&Winner::Two, 100), 34);
+        assert_eq!(Pot::default().payout(&Winner::Two, 100), 100);
+    }
+
+    #[test]
+    fn test_tier_round_trip() {
+        for tier in &[Tier::S, Tier::A, Tier::B, Tier::P, Tier::X] {
+            assert_eq!(tier.to_string().parse::<Tier>(), Ok(*tier));
+        }
+        assert!("".parse::<Tier>().is_err());
+    }
+
+    #[test]
+    fn test_mode_parse() {
+        let cases = [
+            (
+                "82 more matches until the next tournament!",
+                Mode::Matchmaking,
+            ),
+            (
+                "Tournament mode will be activated after the next match!",
+                Mode::Matchmaking,
+            ),
+            ("16 characters are left in the bracket!", Mode::Tournament),
+            (
+                "FINAL ROUND! Stay tuned for exhibitions after the tournament!",
+                Mode::Tournament,
+            ),
+            ("25 exhibition matches left!", Mode::Exhibition),
+            (
+                "Matchmaking mode will be activated after the next exhibition match!",
+                Mode::Exhibition,
+            ),
+            ("", Mode::Matchmaking),
+        ];
+        for (remaining, mode) in &cases {
+            assert_eq!(Mode::parse(remaining, ""), *mode, "{}", remaining);
+        }
+        assert_eq!(Mode::parse("", "Exhibition mode start!"), Mode::Exhibition);
+    }
+
+    #[test]
+    fn test_process_stream_event_mode_from_open() {
+        let open = State {
+            status: String::from("open"),
+            remaining: String::from("1 exhibition matches left!"),
+            ..Default::default()
+        };
+        let decided = State {
+            status: String::from("1"),
+            remaining: String::from("100 more matches until the next tournament!"),
+            ..Default::default()
+        };
+
+        match Game::process_stream_event(decided, &open, None) {
+            Event::Decided(_, fight) => assert_eq!(fight.mode, Mode::Exhibition),
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream() {
+        let remaining = "100 more matches until the next tournament!";
+        let server = mock::Server::start(Script {
+            states: vec![
+                mock::state("open", "one", "two", (0, 0), remaining),
+                mock::state("locked", "one", "two", (1_000, 2_000), remaining),
+                mock::state("1", "one", "two", (0, 0), remaining),
+            ],
+            ..Default::default()
+        })
+        .await;
+        let config = server.config();
+        let http = reqwest::Client::builder()
+            .cookie_store(true)
+            .build()
+            .expect("could not build a client");
+        let client = SaltyBet::new(http, config.clone());
+        client.login().await.expect("could not log in");
+
+        let (outbox, mut inbox) = mpsc::channel(1);
+        tokio::spawn(Game::stream(
+            client,
+            config.poll_interval,
+            Default::default(),
+            outbox,
+        ));
+        let fight = Match {
+            one: String::from("one"),
+            two: String::from("two"),
+            tier: Some(Tier::A),
+            ..Default::default()
+        };
+        let pot = Pot {
+            one: 1_000,
+            two: 2_000,
+        };
+        let (event, _) = inbox.recv().await.expect("the stream stopped");
+        assert_eq!(event, Event::Opened(fight.clone()));
+        let (event, _) = inbox.recv().await.expect("the stream stopped");
+        assert_eq!(event, Event::Locked(pot));
+        let (event, state) = inbox.recv().await.expect("the stream stopped");
+        assert_eq!(event, Event::Decided(Winner::One, Match { pot, ..fight }));
+        assert_eq!(state.status, "1");
+    }
+
+    #[tokio::test]
+    async fn test_stream_picks_up_where_it_left_off() {
+        let remaining = "100 more matches until the next tournament!";
+        let decided = mock::state("1", "one", "two", (0, 0), remaining);
+        let server = mock::Server::start(Script {
+            states: vec![
+                decided.clone(),
+                decided,
+                mock::state("open", "three", "four", (0, 0), remaining),
+            ],
+            ..Default::default()
+        })
+        .await;
+        let config = server.config();
+        let client = SaltyBet::new(reqwest::Client::new(), config.clone());
+
+        // We already dealt with the decided match, so the first thing we hear about is the next.
+        let current_state = client.get_state().await.expect("could not get the state");
+        assert_eq!(current_state.status, "1");
+        let (outbox, mut inbox) = mpsc::channel(1);
+        tokio::spawn(Game::stream(
+            client,
+            config.poll_interval,
+            current_state,
+            outbox,
+        ));
+        match inbox.recv().await.expect("the stream stopped") {
+            (Event::Opened(fight), _) => assert_eq!(fight.one, "three"),
+            (event, _) => panic!("unexpected event: {:?}", event),
+        }
+    }
 }
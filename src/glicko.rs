@@ -0,0 +1,183 @@
+use std::f64::consts::PI;
+
+use crate::elo::Winner;
+
+/// Conversion factor between the Glicko scale and the Glicko-2 scale.
+const SCALE: f64 = 173.7178;
+/// The system constant, which limits how much volatility can change per fight.
+const TAU: f64 = 0.5;
+/// How precisely we solve for the new volatility.
+const EPSILON: f64 = 0.000_001;
+
+/// A Glicko-2 rating. Besides the rating itself we keep track of how sure we are of it (the
+/// deviation) and how erratic the player's results are (the volatility). Every fight is
+/// treated as its own rating period.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glicko {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Glicko {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reduces the impact of a result against an opponent we're unsure about.
+fn g(phi: f64) -> f64 {
+    1f64 / (1f64 + 3f64 * phi * phi / (PI * PI)).sqrt()
+}
+
+/// The expected score of `mu` against an opponent with rating `mu_j` and deviation `phi_j`,
+/// all on the Glicko-2 scale.
+fn e(mu: f64, mu_j: f64, phi_j: f64) -> f64 {
+    1f64 / (1f64 + (-g(phi_j) * (mu - mu_j)).exp())
+}
+
+impl Glicko {
+    /// Create a new `Glicko` struct for a player we know nothing about.
+    pub fn new() -> Self {
+        Self {
+            rating: 1500f64,
+            deviation: 350f64,
+            volatility: 0.06f64,
+        }
+    }
+
+    /// Create a new `Glicko` struct with the given values.
+    pub fn with_rating(rating: f64, deviation: f64, volatility: f64) -> Self {
+        Self {
+            rating,
+            deviation,
+            volatility,
+        }
+    }
+
+    fn mu(&self) -> f64 {
+        (self.rating - 1500f64) / SCALE
+    }
+
+    fn phi(&self) -> f64 {
+        self.deviation / SCALE
+    }
+
+    /// Calculate the chance of `one` beating `two`. The deviations of both players pull the
+    /// result towards 0.5, so we're less confident about players we've rarely seen.
+    pub fn expected(one: &Glicko, two: &Glicko) -> f32 {
+        let phi = (one.phi().powi(2) + two.phi().powi(2)).sqrt();
+        e(one.mu(), two.mu(), phi) as f32
+    }
+
+    /// Update the ratings of both players after a fight.
+    pub fn update_ratings(winner: Winner, one: &mut Glicko, two: &mut Glicko) {
+        let score = match winner {
+            Winner::One => 1f64,
+            Winner::Two => 0f64,
+            Winner::Draw => 0.5f64,
+        };
+
+        // Both updates have to be based on the ratings from before the fight.
+        let (before_one, before_two) = (*one, *two);
+        *one = before_one.rate(&before_two, score);
+        *two = before_two.rate(&before_one, 1f64 - score);
+    }
+
+    /// Step 3 onwards of Glickman's "Example of the Glicko-2 system", for a single opponent.
+    fn rate(&self, opponent: &Glicko, score: f64) -> Glicko {
+        let (mu, phi, sigma) = (self.mu(), self.phi(), self.volatility);
+        let (mu_j, phi_j) = (opponent.mu(), opponent.phi());
+
+        let g_j = g(phi_j);
+        let e_j = e(mu, mu_j, phi_j);
+        let v = 1f64 / (g_j * g_j * e_j * (1f64 - e_j));
+        let delta = v * g_j * (score - e_j);
+
+        // Find the new volatility using the Illinois algorithm.
+        let a = (sigma * sigma).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            ex * (delta * delta - phi * phi - v - ex) / (2f64 * (phi * phi + v + ex).powi(2))
+                - (x - a) / (TAU * TAU)
+        };
+
+        let mut big_a = a;
+        let mut big_b = if delta * delta > phi * phi + v {
+            (delta * delta - phi * phi - v).ln()
+        } else {
+            let mut k = 1f64;
+            while f(a - k * TAU) < 0f64 {
+                k += 1f64;
+            }
+            a - k * TAU
+        };
+
+        let mut f_a = f(big_a);
+        let mut f_b = f(big_b);
+        while (big_b - big_a).abs() > EPSILON {
+            let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+            let f_c = f(big_c);
+            if f_c * f_b <= 0f64 {
+                big_a = big_b;
+                f_a = f_b;
+            } else {
+                f_a /= 2f64;
+            }
+            big_b = big_c;
+            f_b = f_c;
+        }
+
+        let volatility = (big_a / 2f64).exp();
+        let phi_star = (phi * phi + volatility * volatility).sqrt();
+        let phi_new = 1f64 / (1f64 / (phi_star * phi_star) + 1f64 / v).sqrt();
+        let mu_new = mu + phi_new * phi_new * g_j * (score - e_j);
+
+        Glicko {
+            rating: mu_new * SCALE + 1500f64,
+            deviation: phi_new * SCALE,
+            volatility,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glicko_expect_draw() {
+        assert_eq!(Glicko::expected(&Glicko::new(), &Glicko::new()), 0.5f32);
+    }
+
+    #[test]
+    fn test_glicko_deviation_tempers_expectation() {
+        let strong = Glicko::with_rating(1700f64, 50f64, 0.06f64);
+        let unsure = Glicko::with_rating(1700f64, 350f64, 0.06f64);
+        let opponent = Glicko::with_rating(1500f64, 50f64, 0.06f64);
+        assert!(Glicko::expected(&strong, &opponent) > Glicko::expected(&unsure, &opponent));
+    }
+
+    #[test]
+    fn test_glicko_update_against_single_opponent() {
+        // Glickman's worked example, cut down to the first of the three opponents.
+        let mut one = Glicko::with_rating(1500f64, 200f64, 0.06f64);
+        let mut two = Glicko::with_rating(1400f64, 30f64, 0.06f64);
+        Glicko::update_ratings(Winner::One, &mut one, &mut two);
+        assert!((one.rating - 1563.564).abs() < 0.01);
+        assert!((one.deviation - 175.403).abs() < 0.01);
+        assert!((two.rating - 1398.144).abs() < 0.01);
+        assert!((two.deviation - 31.670).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_glicko_deviation_shrinks() {
+        let mut one = Glicko::new();
+        let mut two = Glicko::new();
+        Glicko::update_ratings(Winner::Two, &mut one, &mut two);
+        assert!(one.rating < 1500f64);
+        assert!(two.rating > 1500f64);
+        assert!(one.deviation < 350f64);
+        assert!(two.deviation < 350f64);
+    }
+}
@@ -1 +1,31 @@
+//! Bets on SaltyBet matches by keeping ratings for every character we've seen fight.
+//!
+//! The `waifu-rs` binary is one way of putting the pieces together; they're just as happy to be
+//! used from other tools:
+//!
+//! - `elo`, `glicko` and `rating` rate players, and `team` rates teams of them;
+//! - `state` keeps players, fights and bets in an SQLite database;
+//! - `client` talks to SaltyBet, and `game` turns what it says into a stream of `Event`s;
+//! - `strategy` decides what to bet, and `backtest` and `recompute` replay recorded fights;
+//! - `query` answers questions about what's on record, and `archive` moves it in and out of CSV.
 
+pub mod app;
+pub mod archive;
+pub mod backtest;
+pub mod bet;
+pub mod client;
+pub mod config;
+pub mod elo;
+pub mod error;
+pub mod fight;
+pub mod game;
+pub mod glicko;
+#[cfg(test)]
+mod mock;
+pub mod player;
+pub mod query;
+pub mod rating;
+pub mod recompute;
+pub mod state;
+pub mod strategy;
+pub mod team;
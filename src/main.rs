@@ -1,35 +1,233 @@
-mod app;
-mod config;
-mod elo;
-mod game;
-mod player;
-mod state;
+use waifu_rs::app::App;
+use waifu_rs::client::SaltyBet;
+use waifu_rs::game::Tier;
+use waifu_rs::state::State;
+use waifu_rs::{archive, backtest, config, error, query, rating, recompute, strategy};
 
-use app::App;
-use state::State;
+use clap::{Parser, Subcommand};
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+use std::process;
 
-use std::error::Error;
+/// A bot to place bets on SaltyBet.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// The TOML file to read settings from, instead of W_CONFIG or waifu.toml.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// The sqlite3 database to use, instead of W_FILE_PATH.
+    #[arg(long, global = true)]
+    db: Option<String>,
+
+    /// Watch matches and decide on bets without placing any, instead of W_DRY_RUN.
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Bet on matches as they happen. This is what we do if no command is given.
+    Run,
+    /// Replay the recorded fights through the configured strategy.
+    Backtest,
+    /// Rebuild every rating from the recorded fights.
+    Recompute,
+    /// Show who's likely to win a match, according to each rating system.
+    Predict {
+        one: String,
+        two: String,
+        #[arg(long, value_parser = parse_tier)]
+        tier: Option<Tier>,
+    },
+    /// Show a player's ratings.
+    Player {
+        name: String,
+        #[arg(long, value_parser = parse_tier)]
+        tier: Option<Tier>,
+    },
+    /// List the best rated players.
+    Leaderboard {
+        /// The rating system to rank by, instead of W_RATING.
+        #[arg(long)]
+        system: Option<String>,
+        #[arg(long, value_parser = parse_tier)]
+        tier: Option<Tier>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Show how a player's rating moved over their most recent fights.
+    Timeline {
+        name: String,
+        /// The rating system to show, instead of W_RATING.
+        #[arg(long)]
+        system: Option<String>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Add the fights from a CSV file written by `export`. Run `recompute` afterwards to rate
+    /// them.
+    Import { path: PathBuf },
+    /// Write every recorded fight as CSV, to a file or standard output.
+    Export { path: Option<PathBuf> },
+    /// Look the database over for problems.
+    CheckDb,
+}
+
+fn parse_tier(tier: &str) -> Result<Tier, String> {
+    tier.to_uppercase()
+        .parse()
+        .map_err(|_| String::from("valid tiers are S, A, B, P and X"))
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
+async fn main() {
+    if let Err(error) = run(Cli::parse()).await {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
 
-    // Read from the environment. Maybe swap this out later.
-    let config = config::configure();
+async fn run(cli: Cli) -> error::Result<()> {
+    // Read the config file and the environment, then let flags have the last word.
+    let mut config = config::configure(cli.config.as_deref())?;
+    if let Some(db) = cli.db {
+        config.file_db = db;
+    }
+    if cli.dry_run {
+        config.dry_run = true;
+    }
+    config.validate()?;
+
+    // RUST_LOG still wins over whatever the config file asks for.
+    let filter = config.log.clone().unwrap_or_else(|| String::from("error"));
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(filter)).init();
 
     // We'll need a place to store our state. I made a teeny wrapper to hide
     // some of the implementation deets.
-    let state = State::new(&config).expect("Could not create a database connection.");
+    let mut state = State::new(&config)?;
+
+    // Every rating system we know of is kept up to date, whichever one we bet with.
+    let systems = rating::all(&config);
+
+    // Pick how we'll be betting. The config was validated, so this should never fail.
+    let strategy = strategy::from_config(&config)
+        .ok_or_else(|| error::Error::Config(format!("unknown strategy: {}", config.strategy)))?;
+
+    match cli.command.unwrap_or(Command::Run) {
+        Command::Run => {}
+        Command::Backtest => {
+            // Replay what we've recorded so far, without going anywhere near SaltyBet.
+            let fights = State::get_fights(&state)?;
+            let report = backtest::run(
+                &fights,
+                strategy.as_ref(),
+                &systems,
+                config.backtest_balance,
+                config.rate_exhibitions,
+            );
+            print!("{}", report);
+            return Ok(());
+        }
+        Command::Recompute => {
+            // Rebuild every rating from scratch, e.g. after tweaking how Elo is calculated.
+            let summary = recompute::run(&mut state, &systems, config.rate_exhibitions)?;
+            println!(
+                "Replayed {} fights; {} of {} players changed rating.",
+                summary.fights, summary.changed, summary.players
+            );
+            return Ok(());
+        }
+        Command::Predict { one, two, tier } => {
+            let blend = strategy::Blend::from(&config);
+            print!(
+                "{}",
+                query::predict(&state, &systems, &blend, &one, &two, tier)?
+            );
+            return Ok(());
+        }
+        Command::Player { name, tier } => {
+            print!("{}", State::get_player(&state, &name, tier)?);
+            return Ok(());
+        }
+        Command::Leaderboard {
+            system,
+            tier,
+            limit,
+        } => {
+            let name = system.unwrap_or_else(|| config.rating.clone());
+            let system = rating::from_name(&name, &config)
+                .ok_or_else(|| error::Error::Config(format!("unknown rating: {}", name)))?;
+            print!(
+                "{}",
+                query::leaderboard(&state, system.as_ref(), tier, limit)?
+            );
+            return Ok(());
+        }
+        Command::Timeline {
+            name,
+            system,
+            limit,
+        } => {
+            let system_name = system.unwrap_or_else(|| config.rating.clone());
+            let system = rating::from_name(&system_name, &config)
+                .ok_or_else(|| error::Error::Config(format!("unknown rating: {}", system_name)))?;
+            print!(
+                "{}",
+                query::timeline(&state, system.as_ref(), &name, limit)?
+            );
+            return Ok(());
+        }
+        Command::Import { path } => {
+            let added = archive::import(&mut state, File::open(path)?)?;
+            println!("Imported {} fights. Run recompute to rate them.", added);
+            return Ok(());
+        }
+        Command::Export { path } => {
+            let exported = match path {
+                Some(path) => archive::export(&state, File::create(path)?)?,
+                None => archive::export(&state, io::stdout())?,
+            };
+            eprintln!("Exported {} fights.", exported);
+            return Ok(());
+        }
+        Command::CheckDb => {
+            let health = State::check(&state)?;
+            print!("{}", health);
+            if !health.is_ok() {
+                process::exit(1);
+            }
+            return Ok(());
+        }
+    }
+
+    // Dry runs never bet for real, so they can do without an account.
+    if config.username.is_empty() && !config.dry_run {
+        return Err(error::Error::Config(String::from(
+            "no username; set SB_USERNAME or username in the config file",
+        )));
+    }
+    if config.password.is_empty() && !config.dry_run {
+        return Err(error::Error::Config(String::from(
+            "no password; set SB_PASSWORD, SB_PASSWORD_FILE, or password_file in the config file",
+        )));
+    }
 
     // This client needs to be mutable since we start off with a "clean" client,
     // authenticate (by logging in), and modify it so it stores cookies. This
     // way, when we go to make a subsequent request to place a bet we don't need
     // to worry (too much) about not being logged in.
-    let client = reqwest::Client::builder()
+    let http = reqwest::Client::builder()
         .cookie_store(true)
         .build()
-        .unwrap();
+        .map_err(error::Error::Network)?;
+    let client = SaltyBet::new(http, config.clone());
 
-    let mut app = App::new(config, state, client);
+    let mut app = App::new(config, state, client, strategy, systems);
     app.run().await
 }
@@ -0,0 +1,4 @@
+-- How much was bet on each side when betting was locked. NULL for fights
+-- recorded before we kept track of it.
+ALTER TABLE fights ADD COLUMN one_total INTEGER;
+ALTER TABLE fights ADD COLUMN two_total INTEGER;
@@ -0,0 +1,15 @@
+-- Every bet we try to place. `fight` and `balance_after` are filled in once the
+-- match is decided; `success` is whether SaltyBet accepted the bet.
+CREATE TABLE IF NOT EXISTS bets (
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    placed          DATETIME NOT NULL,
+    fight           INTEGER,
+    side            INTEGER NOT NULL,
+    wager           INTEGER NOT NULL,
+    balance_before  INTEGER NOT NULL,
+    balance_after   INTEGER,
+    probability     REAL NOT NULL,
+    strategy        TEXT NOT NULL,
+    success         INTEGER NOT NULL,
+    FOREIGN KEY (fight) REFERENCES fights(id)
+);
@@ -0,0 +1,12 @@
+-- Ratings for every rating system besides the original Elo, starting with
+-- Glicko-2: one row per player and system. Players without a row start out at
+-- the system's defaults, with a large deviation since we know nothing about them.
+CREATE TABLE IF NOT EXISTS ratings (
+    player      INTEGER NOT NULL,
+    system      TEXT NOT NULL,
+    rating      REAL NOT NULL,
+    deviation   REAL NOT NULL DEFAULT(0),
+    volatility  REAL NOT NULL DEFAULT(0),
+    PRIMARY KEY (player, system),
+    FOREIGN KEY (player) REFERENCES players(id)
+);
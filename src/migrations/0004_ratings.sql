@@ -0,0 +1,15 @@
+-- Elo ratings join every other system in the ratings table, and the elo column
+-- on players goes. SQLite can't be relied on to drop a column, so players is
+-- rebuilt without it, keeping everyone's id.
+INSERT INTO ratings (player, system, rating)
+    SELECT id, 'elo', elo FROM players;
+
+CREATE TABLE players_without_elo (
+    id      INTEGER PRIMARY KEY AUTOINCREMENT,
+    name    TEXT NOT NULL UNIQUE ON CONFLICT REPLACE
+);
+
+INSERT INTO players_without_elo (id, name)
+    SELECT id, name FROM players;
+DROP TABLE players;
+ALTER TABLE players_without_elo RENAME TO players;
@@ -0,0 +1,20 @@
+-- The tier each fight was in, if we know it, and a separate set of ratings for
+-- each tier a player has fought in. Ratings with an empty tier are the ones we
+-- use when we don't know the tier, and the starting point for a new tier.
+ALTER TABLE fights ADD COLUMN tier TEXT;
+
+CREATE TABLE ratings_by_tier (
+    player      INTEGER NOT NULL,
+    system      TEXT NOT NULL,
+    tier        TEXT NOT NULL DEFAULT(''),
+    rating      REAL NOT NULL,
+    deviation   REAL NOT NULL DEFAULT(0),
+    volatility  REAL NOT NULL DEFAULT(0),
+    PRIMARY KEY (player, system, tier),
+    FOREIGN KEY (player) REFERENCES players(id)
+);
+
+INSERT INTO ratings_by_tier (player, system, rating, deviation, volatility)
+    SELECT player, system, rating, deviation, volatility FROM ratings;
+DROP TABLE ratings;
+ALTER TABLE ratings_by_tier RENAME TO ratings;
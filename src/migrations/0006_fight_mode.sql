@@ -0,0 +1,3 @@
+-- Whether each fight was part of matchmaking, a tournament or an exhibition.
+-- Fights recorded before we kept track of this have no mode.
+ALTER TABLE fights ADD COLUMN mode TEXT;
@@ -0,0 +1,9 @@
+-- Which characters fought under a team's name. Teams are kept in the players
+-- table so fights can refer to them, but only their members are rated.
+CREATE TABLE IF NOT EXISTS team_members (
+    team    INTEGER NOT NULL,
+    member  INTEGER NOT NULL,
+    PRIMARY KEY (team, member),
+    FOREIGN KEY (team) REFERENCES players(id),
+    FOREIGN KEY (member) REFERENCES players(id)
+);
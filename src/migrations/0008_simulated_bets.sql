@@ -0,0 +1,2 @@
+-- Bets made during a dry run, which were never sent to SaltyBet.
+ALTER TABLE bets ADD COLUMN simulated INTEGER NOT NULL DEFAULT(0);
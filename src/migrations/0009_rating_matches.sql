@@ -0,0 +1,26 @@
+-- How many fights each rating has been updated after, so new players can be
+-- given a larger Elo K-factor. Existing ratings are counted from the fights on
+-- record in the same tier, and `recompute` gets the exact numbers.
+ALTER TABLE ratings ADD COLUMN matches INTEGER NOT NULL DEFAULT(0);
+
+CREATE TEMP TABLE fight_counts (
+    player      INTEGER NOT NULL,
+    tier        TEXT NOT NULL,
+    matches     INTEGER NOT NULL,
+    PRIMARY KEY (player, tier)
+);
+
+INSERT INTO fight_counts (player, tier, matches)
+    SELECT player, tier, COUNT(*) FROM (
+        SELECT one AS player, COALESCE(tier, '') AS tier FROM fights
+        UNION ALL
+        SELECT two AS player, COALESCE(tier, '') AS tier FROM fights
+    )
+    GROUP BY player, tier;
+
+UPDATE ratings SET matches = COALESCE((
+    SELECT fight_counts.matches FROM fight_counts
+    WHERE fight_counts.player = ratings.player AND fight_counts.tier = ratings.tier
+), 0);
+
+DROP TABLE fight_counts;
@@ -0,0 +1,46 @@
+-- Each player's track record, see `player::Stats`. Existing players are counted
+-- from the fights on record, leaving out teams since their members are the ones
+-- who fought. Streaks start at zero; `recompute` fills them in.
+ALTER TABLE players ADD COLUMN matches INTEGER NOT NULL DEFAULT(0);
+ALTER TABLE players ADD COLUMN wins INTEGER NOT NULL DEFAULT(0);
+ALTER TABLE players ADD COLUMN losses INTEGER NOT NULL DEFAULT(0);
+ALTER TABLE players ADD COLUMN draws INTEGER NOT NULL DEFAULT(0);
+ALTER TABLE players ADD COLUMN first_seen DATETIME;
+ALTER TABLE players ADD COLUMN last_seen DATETIME;
+ALTER TABLE players ADD COLUMN streak INTEGER NOT NULL DEFAULT(0);
+
+CREATE TEMP TABLE player_stats (
+    player      INTEGER PRIMARY KEY,
+    matches     INTEGER NOT NULL,
+    wins        INTEGER NOT NULL,
+    losses      INTEGER NOT NULL,
+    draws       INTEGER NOT NULL,
+    first_seen  DATETIME,
+    last_seen   DATETIME
+);
+
+INSERT INTO player_stats (player, matches, wins, losses, draws, first_seen, last_seen)
+    SELECT player, COUNT(*), SUM(result = 'won'), SUM(result = 'lost'), SUM(result = 'drew'),
+           MIN(ended), MAX(ended)
+    FROM (
+        SELECT one AS player, ended,
+               CASE winner WHEN 1 THEN 'won' WHEN 2 THEN 'lost' ELSE 'drew' END AS result
+        FROM fights
+        UNION ALL
+        SELECT two AS player, ended,
+               CASE winner WHEN 2 THEN 'won' WHEN 1 THEN 'lost' ELSE 'drew' END AS result
+        FROM fights
+    )
+    WHERE player NOT IN (SELECT team FROM team_members)
+    GROUP BY player;
+
+UPDATE players SET
+    matches = (SELECT matches FROM player_stats WHERE player_stats.player = players.id),
+    wins = (SELECT wins FROM player_stats WHERE player_stats.player = players.id),
+    losses = (SELECT losses FROM player_stats WHERE player_stats.player = players.id),
+    draws = (SELECT draws FROM player_stats WHERE player_stats.player = players.id),
+    first_seen = (SELECT first_seen FROM player_stats WHERE player_stats.player = players.id),
+    last_seen = (SELECT last_seen FROM player_stats WHERE player_stats.player = players.id)
+WHERE id IN (SELECT player FROM player_stats);
+
+DROP TABLE player_stats;
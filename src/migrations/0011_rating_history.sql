@@ -0,0 +1,16 @@
+-- Every player's rating in every system before and after each fight they were
+-- rated in, on the side they fought for. Fights recorded before this table
+-- existed have no history until `recompute` is run.
+CREATE TABLE rating_history (
+    fight           INTEGER NOT NULL,
+    player          INTEGER NOT NULL,
+    system          TEXT NOT NULL,
+    side            INTEGER NOT NULL,
+    rating_before   REAL NOT NULL,
+    rating_after    REAL NOT NULL,
+    PRIMARY KEY (fight, player, system),
+    FOREIGN KEY (fight) REFERENCES fights(id),
+    FOREIGN KEY (player) REFERENCES players(id)
+);
+
+CREATE INDEX rating_history_by_player ON rating_history (player, system);
@@ -0,0 +1,2 @@
+-- Head-to-head records look fights up by the two players in them.
+CREATE INDEX fights_by_players ON fights (one, two);
@@ -0,0 +1,256 @@
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use rusqlite::Connection;
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::state::State;
+
+/// The cookie we hand out when someone logs in.
+const SESSION: &str = "PHPSESSID=mock";
+
+/// How the pretend SaltyBet should behave.
+#[derive(Debug, Clone)]
+pub struct Script {
+    /// The `state.json` payloads to serve, one per request. Once we run out, the last one is
+    /// served forever, like a match that never ends.
+    pub states: Vec<String>,
+    /// The balance shown on the index page to anyone logged in.
+    pub balance: u32,
+    /// Whether logging in works.
+    pub login: bool,
+    /// Whether bets from anyone logged in are accepted.
+    pub accept_bets: bool,
+    /// The tier of both players, shown to anyone logged in.
+    pub tier: String,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Script {
+            states: Vec::new(),
+            balance: 1_000,
+            login: true,
+            accept_bets: true,
+            tier: String::from("A"),
+        }
+    }
+}
+
+/// What the server has been asked to do so far.
+#[derive(Debug, Default, Clone)]
+pub struct Seen {
+    pub logins: u32,
+    /// The form bodies of every bet, accepted or not.
+    pub bets: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    script: Script,
+    served: usize,
+    seen: Seen,
+}
+
+/// A pretend SaltyBet running on a random local port, for tests. It speaks just enough HTTP for
+/// reqwest, and serves the endpoints from `Config` following a `Script`.
+pub struct Server {
+    pub url: String,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Server {
+    pub async fn start(script: Script) -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("could not bind the mock server");
+        let url = format!(
+            "http://{}",
+            listener.local_addr().expect("no local address")
+        );
+        let inner = Arc::new(Mutex::new(Inner {
+            script,
+            served: 0,
+            seen: Default::default(),
+        }));
+
+        let shared = inner.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(Self::serve(socket, shared.clone()));
+            }
+        });
+
+        Server { url, inner }
+    }
+
+    /// A configuration pointing every SaltyBet URL at this server.
+    pub fn config(&self) -> Config {
+        Config {
+            username: String::from("waifu@example.com"),
+            password: String::from("hunter2"),
+            file_db: String::from("memory"),
+            url_index: format!("{}/", self.url),
+            url_login: format!("{}/authenticate?signin=1", self.url),
+            url_state: format!("{}/state.json", self.url),
+            url_stats: format!("{}/ajax_get_stats.php", self.url),
+            url_bet: format!("{}/ajax_place_bet.php", self.url),
+            url_referer: format!("{}/", self.url),
+            poll_interval: 10,
+            login_attempts: 3,
+            login_backoff: 10,
+            strategy: String::from("favourite"),
+            rating: String::from("elo"),
+            wager: String::from("flat"),
+            tournament_wager: String::from("all-in"),
+            ..Default::default()
+        }
+    }
+
+    pub fn seen(&self) -> Seen {
+        self.inner.lock().expect("poisoned").seen.clone()
+    }
+
+    /// Answer a single request, then hang up.
+    async fn serve(mut socket: TcpStream, inner: Arc<Mutex<Inner>>) {
+        let (head, body) = match Self::read(&mut socket).await {
+            Some(request) => request,
+            None => return,
+        };
+        let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+        let method = request_line.next().unwrap_or_default();
+        let path = request_line.next().unwrap_or_default();
+        let path = path.split('?').next().unwrap_or_default();
+        let logged_in = head
+            .lines()
+            .any(|line| line.to_lowercase().starts_with("cookie:") && line.contains(SESSION));
+
+        let (status, headers, content) = {
+            let mut inner = inner.lock().expect("poisoned");
+            match (method, path) {
+                ("POST", "/authenticate") => {
+                    inner.seen.logins += 1;
+                    if inner.script.login {
+                        (
+                            "200 OK",
+                            format!("Set-Cookie: {}; Path=/\r\n", SESSION),
+                            String::new(),
+                        )
+                    } else {
+                        (
+                            "200 OK",
+                            String::new(),
+                            String::from("Invalid email or password."),
+                        )
+                    }
+                }
+                ("GET", "/") if logged_in => (
+                    "200 OK",
+                    String::new(),
+                    format!(
+                        r#"<html><span class="dollar" id="balance">{}</span></html>"#,
+                        inner.script.balance
+                    ),
+                ),
+                ("GET", "/") => (
+                    "200 OK",
+                    String::new(),
+                    String::from("<html>Sign in</html>"),
+                ),
+                ("GET", "/state.json") => {
+                    let state = inner
+                        .script
+                        .states
+                        .get(inner.served)
+                        .or_else(|| inner.script.states.last())
+                        .cloned()
+                        .unwrap_or_default();
+                    inner.served += 1;
+                    (
+                        "200 OK",
+                        String::from("Content-Type: application/json\r\n"),
+                        state,
+                    )
+                }
+                ("GET", "/ajax_get_stats.php") if logged_in => (
+                    "200 OK",
+                    String::from("Content-Type: application/json\r\n"),
+                    format!(r#"{{"p1tier":"{0}","p2tier":"{0}"}}"#, inner.script.tier),
+                ),
+                ("GET", "/ajax_get_stats.php") => ("200 OK", String::new(), String::new()),
+                ("POST", "/ajax_place_bet.php") => {
+                    inner.seen.bets.push(body);
+                    if logged_in && inner.script.accept_bets {
+                        ("200 OK", String::new(), String::from("1"))
+                    } else {
+                        ("200 OK", String::new(), String::new())
+                    }
+                }
+                _ => ("404 Not Found", String::new(), String::new()),
+            }
+        };
+
+        Self::respond(socket, status, &headers, &content).await;
+    }
+
+    async fn respond(mut socket: TcpStream, status: &str, headers: &str, content: &str) {
+        let response = format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            headers,
+            content.len(),
+            content
+        );
+        let _ = socket.write_all(response.as_bytes()).await;
+    }
+
+    /// Read the head and body of a request.
+    async fn read(socket: &mut TcpStream) -> Option<(String, String)> {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut chunk = [0u8; 1024];
+        loop {
+            if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+                let head = String::from_utf8_lossy(&buffer[..end]).to_string();
+                let length = head
+                    .lines()
+                    .find(|line| line.to_lowercase().starts_with("content-length:"))
+                    .and_then(|line| line.split(':').nth(1))
+                    .and_then(|length| length.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                while buffer.len() < end + 4 + length {
+                    let read = socket.read(&mut chunk).await.ok()?;
+                    if read == 0 {
+                        return None;
+                    }
+                    buffer.extend_from_slice(&chunk[..read]);
+                }
+                let body = String::from_utf8_lossy(&buffer[end + 4..end + 4 + length]);
+                return Some((head, body.to_string()));
+            }
+
+            let read = socket.read(&mut chunk).await.ok()?;
+            if read == 0 {
+                return None;
+            }
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+/// A `state.json` payload for a match between `one` and `two`.
+pub fn state(status: &str, one: &str, two: &str, pot: (u64, u64), remaining: &str) -> String {
+    format!(
+        r#"{{"p1name":"{}","p2name":"{}","p1total":"{}","p2total":"{}","status":"{}","alert":"","x":0,"remaining":"{}"}}"#,
+        one, two, pot.0, pot.1, status, remaining
+    )
+}
+
+/// A fresh, fully migrated database which only lives in memory.
+pub fn database() -> Result<Connection> {
+    State::new(&Config {
+        file_db: String::from("memory"),
+        ..Default::default()
+    })
+}
@@ -1,26 +1,221 @@
-use crate::elo::Elo;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::elo::Winner;
+use crate::game::Tier;
+use crate::rating::{Rating, RatingSystem};
+use crate::team;
 
 /// Container for holding player information.
 #[derive(Debug, Clone)]
 pub struct Player {
     pub name: String,
-    pub elo: Elo,
+    /// The tier the ratings below are for. `None` holds the ratings we fall back on when we
+    /// don't know the tier of a match.
+    pub tier: Option<Tier>,
+    /// The player's rating in each system, keyed by the system's name.
+    pub ratings: BTreeMap<String, Rating>,
+    /// How the player has done in every fight we've seen, whatever the tier.
+    pub stats: Stats,
+}
+
+/// A player's track record. Team fights count for each member, and exhibitions count too, even
+/// when they aren't rated.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub matches: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    /// When the first and last fights we've seen ended, as stored in the `fights` table.
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+    /// How many fights in a row the player has won, or lost if it's negative. A draw ends
+    /// either kind of streak.
+    pub streak: i32,
+}
+
+impl Stats {
+    /// Count a fight which ended at `ended`, in which the player fought as `side`.
+    pub fn record(&mut self, side: Winner, winner: Winner, ended: &str) {
+        self.matches += 1;
+        if winner == Winner::Draw {
+            self.draws += 1;
+            self.streak = 0;
+        } else if side == winner {
+            self.wins += 1;
+            self.streak = self.streak.max(0) + 1;
+        } else {
+            self.losses += 1;
+            self.streak = self.streak.min(0) - 1;
+        }
+        if self.first_seen.is_none() {
+            self.first_seen = Some(String::from(ended));
+        }
+        self.last_seen = Some(String::from(ended));
+    }
 }
 
 impl Player {
-    pub fn new(name: String, elo: Elo) -> Self {
-        Self { name, elo }
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            tier: None,
+            ratings: BTreeMap::new(),
+            stats: Stats::default(),
+        }
+    }
+
+    /// The player's rating in `system`, or the system's initial rating if we have none yet.
+    pub fn rating(&self, system: &dyn RatingSystem) -> Rating {
+        self.ratings
+            .get(system.name())
+            .copied()
+            .unwrap_or_else(|| system.initial())
+    }
+
+    /// Replace the player's rating in `system`.
+    pub fn set_rating(&mut self, system: &dyn RatingSystem, rating: Rating) {
+        self.ratings.insert(String::from(system.name()), rating);
+    }
+}
+
+impl fmt::Display for Player {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.tier {
+            Some(tier) => writeln!(f, "{} ({} tier)", self.name, tier)?,
+            None => writeln!(f, "{}", self.name)?,
+        }
+        let stats = &self.stats;
+        writeln!(
+            f,
+            "record: {} fights, {} won, {} lost, {} drawn, streak {:+}",
+            stats.matches, stats.wins, stats.losses, stats.draws, stats.streak
+        )?;
+        if let (Some(first), Some(last)) = (&stats.first_seen, &stats.last_seen) {
+            writeln!(f, "seen:   {} to {}", first, last)?;
+        }
+        writeln!(f, "system     rating  deviation  volatility  matches")?;
+        for (system, rating) in &self.ratings {
+            writeln!(
+                f,
+                "{:<8} {:>8.1} {:>10.1} {:>11.4} {:>8}",
+                system, rating.rating, rating.deviation, rating.volatility, rating.matches
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Players kept in memory while replaying fights, one per name and tier.
+#[derive(Debug, Default)]
+pub struct Roster {
+    players: HashMap<(String, Option<Tier>), Player>,
+}
+
+impl Roster {
+    /// Take `name` out of the roster to fight in `tier`. Just like `State::get_player`, someone
+    /// new to a tier starts off with the ratings they have outside of it.
+    pub fn take(&mut self, name: &str, tier: Option<Tier>) -> Player {
+        let key = (String::from(name), tier);
+        if let Some(player) = self.players.remove(&key) {
+            return player;
+        }
+
+        let mut player = self
+            .players
+            .get(&(key.0, None))
+            .cloned()
+            .unwrap_or_else(|| Player::new(String::from(name)));
+        player.tier = tier;
+        player
+    }
+
+    /// Take everyone fighting under `name` out of the roster, see `team::members`.
+    pub fn take_members(&mut self, name: &str, tier: Option<Tier>) -> Vec<Player> {
+        team::members(name)
+            .iter()
+            .map(|member| self.take(member, tier))
+            .collect()
+    }
+
+    /// Put a player back once they're done fighting.
+    pub fn put(&mut self, player: Player) {
+        self.players
+            .insert((player.name.clone(), player.tier), player);
+    }
+
+    /// Put several players back at once.
+    pub fn put_all(&mut self, players: Vec<Player>) {
+        for player in players {
+            self.put(player);
+        }
+    }
+
+    /// Every player in the roster, in no particular order.
+    pub fn into_players(self) -> impl Iterator<Item = Player> {
+        self.players.into_values()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::elo::Elo;
+    use crate::rating::EloSystem;
 
     #[test]
     fn test_player_match() {
-        let white = Player::new(String::from("white"), Elo::new());
-        let black = Player::new(String::from("black"), Elo::new());
-        assert_eq!(Elo::expected(&white.elo, &black.elo), 0.5f32);
+        let white = Player::new(String::from("white"));
+        let black = Player::new(String::from("black"));
+        assert_eq!(EloSystem::default().expected(&white, &black), 0.5f32);
+    }
+
+    #[test]
+    fn test_stats_record() {
+        let mut stats = Stats::default();
+        stats.record(Winner::One, Winner::One, "2021-01-01 00:00:00");
+        stats.record(Winner::Two, Winner::Two, "2021-01-01 00:05:00");
+        assert_eq!(stats.streak, 2);
+        stats.record(Winner::One, Winner::Two, "2021-01-01 00:10:00");
+        assert_eq!(stats.streak, -1);
+        stats.record(Winner::One, Winner::Draw, "2021-01-01 00:15:00");
+        assert_eq!(
+            stats,
+            Stats {
+                matches: 4,
+                wins: 2,
+                losses: 1,
+                draws: 1,
+                first_seen: Some(String::from("2021-01-01 00:00:00")),
+                last_seen: Some(String::from("2021-01-01 00:15:00")),
+                streak: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_roster_seeds_new_tier() {
+        let mut roster = Roster::default();
+        let mut player = roster.take("white", None);
+        player.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1200)));
+        roster.put(player);
+
+        let mut tiered = roster.take("white", Some(Tier::A));
+        assert_eq!(tiered.tier, Some(Tier::A));
+        assert_eq!(
+            tiered.rating(&EloSystem::default()),
+            Rating::from(Elo::with_rating(1200))
+        );
+        tiered.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(900)));
+        roster.put(tiered);
+
+        // The untiered rating is left alone.
+        let untiered = roster.take("white", None);
+        assert_eq!(
+            untiered.rating(&EloSystem::default()),
+            Rating::from(Elo::with_rating(1200))
+        );
+        assert_eq!(roster.into_players().count(), 1);
     }
 }
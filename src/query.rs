@@ -0,0 +1,351 @@
+use rusqlite::Connection;
+use std::fmt;
+
+use crate::elo::Winner;
+use crate::error::Result;
+use crate::fight::HeadToHead;
+use crate::game::Tier;
+use crate::player::Player;
+use crate::rating::RatingSystem;
+use crate::state::{Snapshot, State};
+use crate::strategy::Blend;
+use crate::team;
+
+/// How a match between two sides would go, according to each rating system.
+#[derive(Debug, Clone)]
+pub struct Matchup {
+    pub one: Player,
+    pub two: Player,
+    /// How `one` has done against `two` before.
+    pub head_to_head: HeadToHead,
+    /// The chance of `one` winning in each system, by the system's name, taking the
+    /// head-to-head record into account.
+    pub chances: Vec<(String, f32)>,
+}
+
+impl fmt::Display for Matchup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.one.tier {
+            Some(tier) => writeln!(f, "{} vs {} ({} tier)", self.one.name, self.two.name, tier)?,
+            None => writeln!(f, "{} vs {}", self.one.name, self.two.name)?,
+        }
+        let record = &self.head_to_head;
+        if record.meetings() > 0 {
+            writeln!(
+                f,
+                "head to head: {} won, {} lost, {} drawn",
+                record.wins, record.losses, record.draws
+            )?;
+        }
+        for (system, chance) in &self.chances {
+            let (favourite, chance) = if *chance >= 0.5f32 {
+                (&self.one.name, *chance)
+            } else {
+                (&self.two.name, 1f32 - chance)
+            };
+            writeln!(f, "{:<8} {} ({:.1}%)", system, favourite, chance * 100f32)?;
+        }
+        Ok(())
+    }
+}
+
+/// Work out how a match between `one` and `two` in `tier` would go from what we have on record.
+/// Either side may be a team. Each system's prediction is blended with the two sides' record
+/// against each other as `blend` says.
+pub fn predict(
+    state: &Connection,
+    systems: &[Box<dyn RatingSystem>],
+    blend: &Blend,
+    one: &str,
+    two: &str,
+    tier: Option<Tier>,
+) -> Result<Matchup> {
+    let one_members = State::get_members(state, one, tier)?;
+    let one = team::composite(one, tier, &one_members, systems);
+    let two_members = State::get_members(state, two, tier)?;
+    let two = team::composite(two, tier, &two_members, systems);
+    let head_to_head = State::get_head_to_head(state, &one.name, &two.name)?;
+    let chances = systems
+        .iter()
+        .map(|system| {
+            let expected = system.expected(&one, &two);
+            (
+                String::from(system.name()),
+                blend.apply(expected, &head_to_head),
+            )
+        })
+        .collect();
+    Ok(Matchup {
+        one,
+        two,
+        head_to_head,
+        chances,
+    })
+}
+
+/// The best rated players in a single system.
+#[derive(Debug, Clone)]
+pub struct Leaderboard {
+    pub system: String,
+    pub tier: Option<Tier>,
+    /// Best first.
+    pub players: Vec<Player>,
+}
+
+impl fmt::Display for Leaderboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.tier {
+            Some(tier) => writeln!(f, "rank   {:>8}  name ({} tier)", self.system, tier)?,
+            None => writeln!(f, "rank   {:>8}  name", self.system)?,
+        }
+        for (rank, player) in self.players.iter().enumerate() {
+            let rating = player
+                .ratings
+                .get(&self.system)
+                .map(|rating| rating.rating)
+                .unwrap_or_default();
+            writeln!(f, "{:>4} {:>10.1}  {}", rank + 1, rating, player.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// The `limit` best players in `tier` according to `system`. Teams, and players the system
+/// hasn't rated in the tier yet, are left out.
+pub fn leaderboard(
+    state: &Connection,
+    system: &dyn RatingSystem,
+    tier: Option<Tier>,
+    limit: usize,
+) -> Result<Leaderboard> {
+    let mut players = Vec::new();
+    for player in State::get_players(state, tier)? {
+        if team::is_team(&player.name) {
+            continue;
+        }
+        if player.ratings.contains_key(system.name()) {
+            players.push(player);
+        }
+    }
+
+    players.sort_by(|a, b| {
+        b.rating(system)
+            .rating
+            .partial_cmp(&a.rating(system).rating)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    players.truncate(limit);
+    Ok(Leaderboard {
+        system: String::from(system.name()),
+        tier,
+        players,
+    })
+}
+
+/// How a player's rating in a single system moved over their most recent fights.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    pub player: Player,
+    pub system: String,
+    /// Oldest first.
+    pub history: Vec<Snapshot>,
+}
+
+impl fmt::Display for Timeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stats = &self.player.stats;
+        writeln!(
+            f,
+            "{} ({}): {} fights, {} won, {} lost, {} drawn, streak {:+}",
+            self.player.name,
+            self.system,
+            stats.matches,
+            stats.wins,
+            stats.losses,
+            stats.draws,
+            stats.streak
+        )?;
+        writeln!(
+            f,
+            "ended                tier  result    before     after   change  opponent"
+        )?;
+        for snapshot in &self.history {
+            let result = if snapshot.winner == Winner::Draw {
+                "drew"
+            } else if snapshot.winner == snapshot.side {
+                "won"
+            } else {
+                "lost"
+            };
+            let tier = snapshot
+                .tier
+                .map(|tier| tier.to_string())
+                .unwrap_or_else(|| String::from("-"));
+            writeln!(
+                f,
+                "{:<20} {:<5} {:<6} {:>9.1} {:>9.1} {:>+8.1}  {}",
+                snapshot.ended,
+                tier,
+                result,
+                snapshot.before,
+                snapshot.after,
+                snapshot.after - snapshot.before,
+                snapshot.opponent
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How `name`'s rating in `system` moved over their `limit` most recent rated fights.
+pub fn timeline(
+    state: &Connection,
+    system: &dyn RatingSystem,
+    name: &str,
+    limit: usize,
+) -> Result<Timeline> {
+    Ok(Timeline {
+        player: State::get_player(state, &String::from(name), None)?,
+        system: String::from(system.name()),
+        history: State::get_history(state, name, system.name(), limit)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::elo::Elo;
+    use crate::game::{Event, Match};
+    use crate::mock;
+    use crate::rating::{self, EloSystem, Rating};
+
+    fn state() -> Result<Connection> {
+        let state = mock::database()?;
+        for (name, elo) in &[("Ryu", 1200), ("Ken", 1000), ("Akuma", 1400)] {
+            let mut player = Player::new(String::from(*name));
+            player.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(*elo)));
+            State::put_player(&state, &player)?;
+        }
+        State::put_player(&state, &Player::new(String::from("Dan")))?;
+        State::put_team(
+            &state,
+            "Ryu / Ken",
+            &[String::from("Ryu"), String::from("Ken")],
+        )?;
+        Ok(state)
+    }
+
+    #[test]
+    fn test_predict() -> Result<()> {
+        let state = state()?;
+        let systems = rating::all(&Config::default());
+        let blend = Blend {
+            min_meetings: 3,
+            weight: 1f32,
+        };
+
+        let matchup = predict(&state, &systems, &blend, "Ryu", "Ken", None)?;
+        assert_eq!(matchup.chances[0].0, "elo");
+        assert!(matchup.chances[0].1 > 0.5f32);
+
+        // Ken has Ryu's number, whatever the ratings say.
+        for _ in 0..3 {
+            State::put_event(
+                &state,
+                &Event::Decided(
+                    Winner::Two,
+                    Match {
+                        one: String::from("Ryu"),
+                        two: String::from("Ken"),
+                        ..Default::default()
+                    },
+                ),
+            )?;
+        }
+        let matchup = predict(&state, &systems, &blend, "Ryu", "Ken", None)?;
+        assert_eq!(matchup.head_to_head.losses, 3);
+        assert_eq!(matchup.chances[0].1, 0.2f32);
+        assert!(matchup.to_string().contains("head to head: 0 won, 3 lost"));
+
+        // Teams fight as the average of their members.
+        let matchup = predict(&state, &systems, &blend, "Ryu / Ken", "Akuma", None)?;
+        assert_eq!(
+            matchup.one.rating(&EloSystem::default()),
+            Rating::from(Elo::with_rating(1100))
+        );
+        assert!(matchup.chances[0].1 < 0.5f32);
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeline() -> Result<()> {
+        let state = state()?;
+        let systems = rating::all(&Config::default());
+        let before = State::get_members(&state, "Ryu", None)?;
+        let mut after = before.clone();
+        after[0].set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1216)));
+        for fight in 0..3 {
+            State::put_event(
+                &state,
+                &Event::Decided(
+                    Winner::One,
+                    Match {
+                        one: String::from("Ryu"),
+                        two: String::from("Ken"),
+                        ..Default::default()
+                    },
+                ),
+            )?;
+            if fight > 0 {
+                let id = state.last_insert_rowid();
+                State::put_history(&state, id, Winner::One, &systems, &before, &after)?;
+            }
+        }
+
+        let timeline = timeline(&state, &EloSystem::default(), "Ryu", 1)?;
+        assert_eq!(timeline.player.stats.wins, 3);
+        assert_eq!(timeline.history.len(), 1);
+        let snapshot = &timeline.history[0];
+        assert_eq!(snapshot.fight, 3);
+        assert_eq!((snapshot.before, snapshot.after), (1200f64, 1216f64));
+        assert_eq!(snapshot.opponent, "Ken");
+        assert!(timeline.to_string().contains("won"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_leaderboard() -> Result<()> {
+        let state = state()?;
+        let leaderboard = leaderboard(&state, &EloSystem::default(), None, 2)?;
+        let names: Vec<&str> = leaderboard
+            .players
+            .iter()
+            .map(|player| player.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Akuma", "Ryu"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_leaderboard_tiered() -> Result<()> {
+        let state = state()?;
+        let mut ken = State::get_player(&state, &String::from("Ken"), Some(Tier::A))?;
+        ken.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1100)));
+        State::put_player(&state, &ken)?;
+
+        // Only Ken has fought in A tier, so nobody else is ranked there.
+        let leaderboard = leaderboard(&state, &EloSystem::default(), Some(Tier::A), 10)?;
+        let names: Vec<&str> = leaderboard
+            .players
+            .iter()
+            .map(|player| player.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Ken"]);
+        assert_eq!(
+            leaderboard.players[0].rating(&EloSystem::default()).rating,
+            1100f64
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use crate::config::Config;
+use crate::elo::{self, Elo, Winner};
+use crate::glicko::Glicko;
+use crate::player::Player;
+
+/// A player's standing in some rating system, in the shape it's kept in the `ratings` table.
+/// Systems which don't track how sure they are of a rating leave `deviation` and `volatility`
+/// at zero.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    /// How many fights the rating has been updated after.
+    pub matches: u32,
+}
+
+/// A way of rating players from the outcome of their fights. Every system keeps its own
+/// `Rating` per player, so several of them can follow the same fights side by side.
+pub trait RatingSystem {
+    /// The name used to select the system in the configuration and to store its ratings.
+    fn name(&self) -> &'static str;
+
+    /// The rating of a player we know nothing about.
+    fn initial(&self) -> Rating;
+
+    /// The chance of `one` beating `two`.
+    fn expected(&self, one: &Player, two: &Player) -> f32;
+
+    /// Update the ratings of both players after a fight.
+    fn update(&self, winner: Winner, one: &mut Player, two: &mut Player);
+}
+
+impl From<Rating> for Elo {
+    fn from(item: Rating) -> Self {
+        Elo {
+            rating: item.rating as i32,
+            matches: item.matches,
+        }
+    }
+}
+
+impl From<Elo> for Rating {
+    fn from(item: Elo) -> Self {
+        Rating {
+            rating: item.rating as f64,
+            matches: item.matches,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Rating> for Glicko {
+    fn from(item: Rating) -> Self {
+        Glicko::with_rating(item.rating, item.deviation, item.volatility)
+    }
+}
+
+impl From<Glicko> for Rating {
+    fn from(item: Glicko) -> Self {
+        Rating {
+            rating: item.rating,
+            deviation: item.deviation,
+            volatility: item.volatility,
+            matches: 0,
+        }
+    }
+}
+
+/// Plain Elo, see `elo::Elo`, with a larger K-factor for players we've rarely seen.
+#[derive(Default)]
+pub struct EloSystem {
+    pub params: elo::Params,
+}
+
+impl From<&Config> for EloSystem {
+    fn from(config: &Config) -> Self {
+        EloSystem {
+            params: elo::Params {
+                scale: config.elo_scale,
+                k_factor: config.elo_k_factor,
+                provisional_k_factor: config.elo_provisional_k_factor,
+                provisional_matches: config.elo_provisional_matches,
+            },
+        }
+    }
+}
+
+impl RatingSystem for EloSystem {
+    fn name(&self) -> &'static str {
+        "elo"
+    }
+
+    fn initial(&self) -> Rating {
+        Rating::from(Elo::new())
+    }
+
+    fn expected(&self, one: &Player, two: &Player) -> f32 {
+        self.params
+            .expected(&one.rating(self).into(), &two.rating(self).into())
+    }
+
+    fn update(&self, winner: Winner, one: &mut Player, two: &mut Player) {
+        let mut one_elo = Elo::from(one.rating(self));
+        let mut two_elo = Elo::from(two.rating(self));
+        self.params
+            .update_ratings(winner, &mut one_elo, &mut two_elo);
+        one.set_rating(self, one_elo.into());
+        two.set_rating(self, two_elo.into());
+    }
+}
+
+/// Glicko-2, see `glicko::Glicko`. Players we've rarely seen get predictions closer to a coin
+/// flip, which also makes Kelly sizing more careful with them.
+pub struct GlickoSystem {}
+
+impl RatingSystem for GlickoSystem {
+    fn name(&self) -> &'static str {
+        "glicko2"
+    }
+
+    fn initial(&self) -> Rating {
+        Rating::from(Glicko::new())
+    }
+
+    fn expected(&self, one: &Player, two: &Player) -> f32 {
+        Glicko::expected(&one.rating(self).into(), &two.rating(self).into())
+    }
+
+    fn update(&self, winner: Winner, one: &mut Player, two: &mut Player) {
+        let (one_rating, two_rating) = (one.rating(self), two.rating(self));
+        let mut one_glicko = Glicko::from(one_rating);
+        let mut two_glicko = Glicko::from(two_rating);
+        Glicko::update_ratings(winner, &mut one_glicko, &mut two_glicko);
+        one.set_rating(
+            self,
+            Rating {
+                matches: one_rating.matches + 1,
+                ..one_glicko.into()
+            },
+        );
+        two.set_rating(
+            self,
+            Rating {
+                matches: two_rating.matches + 1,
+                ..two_glicko.into()
+            },
+        );
+    }
+}
+
+/// Look up a rating system by name, set up as `config` says.
+pub fn from_name(name: &str, config: &Config) -> Option<Box<dyn RatingSystem>> {
+    match name {
+        "elo" => Some(Box::new(EloSystem::from(config))),
+        "glicko2" => Some(Box::new(GlickoSystem {})),
+        _ => None,
+    }
+}
+
+/// Every rating system we know about, set up as `config` says. These are all kept up to date as
+/// fights happen, no matter which one we use for betting.
+pub fn all(config: &Config) -> Vec<Box<dyn RatingSystem>> {
+    vec![Box::new(EloSystem::from(config)), Box::new(GlickoSystem {})]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_systems_are_independent() {
+        let mut one = Player::new(String::from("one"));
+        let mut two = Player::new(String::from("two"));
+        let elo = EloSystem::default();
+        let glicko = GlickoSystem {};
+
+        elo.update(Winner::One, &mut one, &mut two);
+        assert!(elo.expected(&one, &two) > 0.5f32);
+        assert_eq!(glicko.expected(&one, &two), 0.5f32);
+        assert_eq!(one.rating(&glicko), glicko.initial());
+    }
+
+    #[test]
+    fn test_rating_round_trip() {
+        let glicko = Glicko::with_rating(1600f64, 80f64, 0.05f64);
+        assert_eq!(Glicko::from(Rating::from(glicko)), glicko);
+        assert_eq!(Elo::from(Rating::from(Elo::with_rating(1234))).rating, 1234);
+    }
+
+    #[test]
+    fn test_from_name() {
+        let config = Config::default();
+        for system in all(&config) {
+            assert_eq!(
+                from_name(system.name(), &config).map(|s| s.name()),
+                Some(system.name())
+            );
+        }
+        assert!(from_name("nonsense", &config).is_none());
+    }
+
+    #[test]
+    fn test_systems_count_matches() {
+        let mut one = Player::new(String::from("one"));
+        let mut two = Player::new(String::from("two"));
+        for system in all(&Config::default()) {
+            system.update(Winner::One, &mut one, &mut two);
+            system.update(Winner::Two, &mut one, &mut two);
+            assert_eq!(one.rating(system.as_ref()).matches, 2);
+            assert_eq!(two.rating(system.as_ref()).matches, 2);
+        }
+    }
+
+    #[test]
+    fn test_elo_from_config() {
+        let config = Config {
+            elo_k_factor: 10f32,
+            elo_provisional_matches: 0,
+            ..Default::default()
+        };
+        let mut one = Player::new(String::from("one"));
+        let mut two = Player::new(String::from("two"));
+        EloSystem::from(&config).update(Winner::One, &mut one, &mut two);
+        assert_eq!(one.rating(&EloSystem::default()).rating, 1005f64);
+    }
+}
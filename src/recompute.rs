@@ -0,0 +1,204 @@
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+
+use crate::elo::Winner;
+use crate::error::Result;
+use crate::fight::Fight;
+use crate::game::Mode;
+use crate::player::{Player, Roster, Stats};
+use crate::rating::RatingSystem;
+use crate::state::State;
+use crate::team;
+
+/// What happened when ratings were rebuilt.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Summary {
+    pub fights: usize,
+    pub players: usize,
+    pub changed: usize,
+}
+
+/// Throw away every player's rating in each of the `systems` and rebuild them by replaying the
+/// `fights` table from the beginning, tier by tier, rating team members rather than teams.
+/// Exhibitions are left out unless `rate_exhibitions` is set. Players who never fought end up
+/// back at each system's initial rating. Everyone's `Stats` and rating history are rebuilt
+/// along the way, the former from every fight. Everything is written in a single transaction,
+/// so a failure leaves the old ratings untouched.
+pub fn run(
+    state: &mut Connection,
+    systems: &[Box<dyn RatingSystem>],
+    rate_exhibitions: bool,
+) -> Result<Summary> {
+    let tx = state.transaction()?;
+    let fights = State::get_fights(&tx)?;
+
+    let mut stats: HashMap<String, Stats> = HashMap::new();
+    for fight in &fights {
+        for (side, name) in &[(Winner::One, &fight.one), (Winner::Two, &fight.two)] {
+            for member in team::members(name) {
+                stats
+                    .entry(member)
+                    .or_default()
+                    .record(*side, fight.winner, &fight.ended);
+            }
+        }
+    }
+
+    let fights: Vec<Fight> = fights
+        .into_iter()
+        .filter(|fight| rate_exhibitions || fight.mode != Some(Mode::Exhibition))
+        .collect();
+
+    tx.execute("DELETE FROM rating_history;", rusqlite::NO_PARAMS)?;
+    let mut replayed = Roster::default();
+    for fight in &fights {
+        let mut one = replayed.take_members(&fight.one, fight.tier);
+        let mut two = replayed.take_members(&fight.two, fight.tier);
+        let (one_before, two_before) = (one.clone(), two.clone());
+        team::update(systems, fight.winner, &mut one, &mut two);
+        State::put_history(&tx, fight.id, Winner::One, systems, &one_before, &one)?;
+        State::put_history(&tx, fight.id, Winner::Two, systems, &two_before, &two)?;
+        replayed.put_all(one);
+        replayed.put_all(two);
+    }
+
+    let current = State::get_players(&tx, None)?;
+    let mut summary = Summary {
+        fights: fights.len(),
+        players: current.len(),
+        ..Default::default()
+    };
+
+    // Line up what we rebuilt with what we have saved, before we throw the latter away.
+    let mut rebuilt: Vec<(Player, Player)> = Vec::new();
+    let mut changed: HashSet<String> = HashSet::new();
+    for player in current {
+        // Only a team's members are rated, so whatever a team had before goes.
+        if team::is_team(&player.name) {
+            if !player.ratings.is_empty() {
+                changed.insert(player.name);
+            }
+            continue;
+        }
+
+        let mut untiered = replayed.take(&player.name, None);
+        // Players who never fought get a fresh start in every system.
+        for system in systems {
+            let rating = untiered.rating(system.as_ref());
+            untiered.set_rating(system.as_ref(), rating);
+        }
+        rebuilt.push((player, untiered));
+    }
+    for tiered in replayed.into_players() {
+        let saved = State::get_player(&tx, &tiered.name, tiered.tier)?;
+        rebuilt.push((saved, tiered));
+    }
+
+    for (saved, player) in &rebuilt {
+        let differs = systems
+            .iter()
+            .any(|system| saved.rating(system.as_ref()) != player.rating(system.as_ref()));
+        if differs {
+            changed.insert(player.name.clone());
+        }
+    }
+    summary.changed = changed.len();
+
+    tx.execute("DELETE FROM ratings;", rusqlite::NO_PARAMS)?;
+    for (_, player) in &rebuilt {
+        State::put_player(&tx, player)?;
+    }
+    for player in State::get_players(&tx, None)? {
+        let rebuilt = stats.remove(&player.name).unwrap_or_default();
+        State::put_stats(&tx, &player.name, &rebuilt)?;
+    }
+
+    tx.commit()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::elo::{Elo, Winner};
+    use crate::game::{Event, Match};
+    use crate::mock;
+    use crate::rating::{self, EloSystem, GlickoSystem, Rating};
+
+    #[test]
+    fn test_recompute_replays_fights() -> Result<()> {
+        let mut state = mock::database()?;
+
+        // Ratings which don't line up with the one fight on record.
+        let mut one = Player::new(String::from("one"));
+        one.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1500)));
+        let mut two = Player::new(String::from("two"));
+        two.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(500)));
+        State::put_player(&state, &one)?;
+        State::put_player(&state, &two)?;
+        State::put_player(&state, &Player::new(String::from("idle")))?;
+        State::put_event(
+            &state,
+            &Event::Decided(
+                Winner::Two,
+                Match {
+                    one: String::from("one"),
+                    two: String::from("two"),
+                    ..Default::default()
+                },
+            ),
+        )?;
+        // Exhibitions are left out.
+        State::put_event(
+            &state,
+            &Event::Decided(
+                Winner::One,
+                Match {
+                    one: String::from("one"),
+                    two: String::from("two"),
+                    mode: Mode::Exhibition,
+                    ..Default::default()
+                },
+            ),
+        )?;
+
+        let systems = rating::all(&Config::default());
+        let summary = run(&mut state, &systems, false)?;
+        assert_eq!(
+            summary,
+            Summary {
+                fights: 1,
+                players: 3,
+                changed: 2,
+            }
+        );
+
+        let mut one = Player::new(String::from("one"));
+        let mut two = Player::new(String::from("two"));
+        for system in &systems {
+            system.update(Winner::Two, &mut one, &mut two);
+        }
+        let saved_one = State::get_player(&state, &String::from("one"), None)?;
+        let saved_two = State::get_player(&state, &String::from("two"), None)?;
+        let saved_idle = State::get_player(&state, &String::from("idle"), None)?;
+        assert_eq!(saved_one.ratings, one.ratings);
+        assert_eq!(saved_two.ratings, two.ratings);
+        // Only the rated fight has a history.
+        let history = State::get_history(&state, "two", "elo", 10)?;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].after, two.rating(&EloSystem::default()).rating);
+        assert_eq!(
+            saved_idle.rating(&GlickoSystem {}),
+            GlickoSystem {}.initial()
+        );
+
+        // Exhibitions still count towards everyone's record.
+        State::put_stats(&state, "one", &Stats::default())?;
+        run(&mut state, &systems, false)?;
+        let stats = State::get_stats(&state, "one")?;
+        assert_eq!((stats.matches, stats.wins, stats.streak), (2, 1, 1));
+        assert_eq!(State::get_stats(&state, "idle")?, Stats::default());
+        Ok(())
+    }
+}
@@ -1,148 +1,1065 @@
-use log::{error, trace, warn};
-use rusqlite::{named_params, Connection};
-use std::error::Error;
+use log::trace;
+use rusqlite::{named_params, Connection, OptionalExtension};
 use std::fmt;
 use std::path::Path;
-use std::result;
 
+use crate::bet::Bet;
 use crate::config::Config;
-use crate::elo::Elo;
-use crate::game::Event;
-use crate::player::Player;
+use crate::elo::Winner;
+use crate::error::{Error, Result};
+use crate::fight::{Fight, HeadToHead};
+use crate::game::{Event, Pot, Tier};
+use crate::player::{Player, Stats};
+use crate::rating::{Rating, RatingSystem};
+use crate::team;
 
-/// This struct is really just a wrapper for some functions which manage storing
-/// our state.  In reality, they mostly take a `&rusqlite::Connection` as their
-/// first argument.
-pub struct State {}
+/// Changes to the schema made after `schema.sql` was first written, in the order they have to
+/// be applied. `PRAGMA user_version` holds how many of them a database has seen, which lets us
+/// upgrade databases seeded from `contrib/data.sql` as well as ones we created.
+const MIGRATIONS: &[&str] = &[
+    include_str!("migrations/0001_fight_pot.sql"),
+    include_str!("migrations/0002_bets.sql"),
+    include_str!("migrations/0003_glicko.sql"),
+    include_str!("migrations/0004_ratings.sql"),
+    include_str!("migrations/0005_tiers.sql"),
+    include_str!("migrations/0006_fight_mode.sql"),
+    include_str!("migrations/0007_teams.sql"),
+    include_str!("migrations/0008_simulated_bets.sql"),
+    include_str!("migrations/0009_rating_matches.sql"),
+    include_str!("migrations/0010_player_stats.sql"),
+    include_str!("migrations/0011_rating_history.sql"),
+    include_str!("migrations/0012_fight_pairs.sql"),
+];
 
-/// I apologize for the long word.
-#[derive(Debug, Default)]
-struct NoValidDatabaseError {}
-impl Error for NoValidDatabaseError {}
-impl fmt::Display for NoValidDatabaseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Valid options are: 'memory' and a path to a file.")
+/// What `State::check` found out about a database.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Health {
+    /// What SQLite's integrity check had to say, which is just "ok" if all is well.
+    pub integrity: Vec<String>,
+    /// How many `MIGRATIONS` the database has seen, and how many there are.
+    pub version: usize,
+    pub latest: usize,
+    pub players: i64,
+    pub fights: i64,
+    pub bets: i64,
+    /// Fights which refer to players we don't have.
+    pub orphaned_fights: i64,
+    /// Bets which were never tied to a fight, e.g. because we stopped before it ended.
+    pub unsettled_bets: i64,
+}
+
+impl Health {
+    /// Whether the database is fit to use. Unsettled bets are expected every now and then, so
+    /// they don't count.
+    pub fn is_ok(&self) -> bool {
+        self.integrity == ["ok"] && self.version == self.latest && self.orphaned_fights == 0
     }
 }
 
-/// I apologize for the long word.
-#[derive(Debug, Default)]
-struct CouldNotCreateDatabaseConnectionError {}
-impl Error for CouldNotCreateDatabaseConnectionError {}
-impl fmt::Display for CouldNotCreateDatabaseConnectionError {
+impl fmt::Display for Health {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Couldn't create a database connection.")
+        writeln!(f, "integrity:       {}", self.integrity.join("; "))?;
+        writeln!(f, "schema version:  {} of {}", self.version, self.latest)?;
+        writeln!(f, "players:         {}", self.players)?;
+        writeln!(f, "fights:          {}", self.fights)?;
+        writeln!(f, "bets:            {}", self.bets)?;
+        writeln!(f, "orphaned fights: {}", self.orphaned_fights)?;
+        writeln!(f, "unsettled bets:  {}", self.unsettled_bets)
     }
 }
 
+/// How a fight changed a player's rating in one system, see `State::get_history`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub fight: i64,
+    pub ended: String,
+    pub tier: Option<Tier>,
+    /// The side the player fought on.
+    pub side: Winner,
+    pub winner: Winner,
+    /// Whoever fought on the other side, which may be a team.
+    pub opponent: String,
+    pub before: f64,
+    pub after: f64,
+}
+
+/// This struct is really just a wrapper for some functions which manage storing
+/// our state.  In reality, they mostly take a `&rusqlite::Connection` as their
+/// first argument.
+pub struct State {}
+
 impl State {
     /// We only know about two kinds of "database storage" methods:
     /// - memory: don't persist anything;
     /// - on disk: persist everything to an SQLite3 database.
-    pub fn new(config: &Config) -> result::Result<Connection, Box<dyn Error>> {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(config: &Config) -> Result<Connection> {
         let state = match config.file_db.as_str() {
             "memory" => Connection::open_in_memory()?,
-            path => Connection::open(&Path::new(path))?,
+            path => Connection::open(Path::new(path))?,
         };
 
-        if let Err(_) = state.execute_batch(include_str!("schema.sql")) {
-            error!("Could not create schema.");
-            Err(Box::new(CouldNotCreateDatabaseConnectionError {}))
-        } else {
-            trace!("Connection created (schema was created).");
-            Ok(state)
+        state.execute_batch(include_str!("schema.sql"))?;
+        State::migrate(&state)?;
+        trace!("Connection created (schema was created).");
+        Ok(state)
+    }
+
+    /// Apply any `MIGRATIONS` the database hasn't seen yet. Each one runs in its own
+    /// transaction along with the bump of the schema version.
+    fn migrate(state: &Connection) -> rusqlite::Result<()> {
+        let version: usize =
+            state.query_row("PRAGMA user_version;", rusqlite::NO_PARAMS, |row| {
+                row.get::<_, i64>(0)
+            })? as usize;
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            trace!("Applying migration {}", index + 1);
+            state.execute_batch(&format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                migration,
+                index + 1
+            ))?;
         }
+
+        Ok(())
     }
 
-    /// Get the specified player or return a default `Player`.
-    pub fn get_player(state: &Connection, name: &String) -> Player {
-        let player = state
-            .prepare("SELECT name, elo FROM players WHERE name = :name;")
-            .and_then(|mut stmt| {
-                stmt.query_row_named(named_params! { ":name": name}, |row| {
-                    Ok(Player::new(row.get(0)?, Elo::with_rating(row.get(1)?)))
-                })
-            });
+    /// Get the specified player's ratings in `tier` and their `Stats`, or return a default
+    /// `Player`. Where they haven't been rated in the tier yet, we start from their untiered
+    /// ratings.
+    pub fn get_player(state: &Connection, name: &String, tier: Option<Tier>) -> Result<Player> {
+        let mut player = Player::new(name.to_string());
+        player.tier = tier;
+        player.stats = State::get_stats(state, name)?;
+        let mut stmt = state.prepare(
+            "
+            SELECT ratings.system, ratings.rating, ratings.deviation, ratings.volatility,
+                   ratings.matches
+            FROM ratings
+            JOIN players ON players.id = ratings.player
+            WHERE players.name = :name AND ratings.tier IN ('', :tier)
+            ORDER BY ratings.tier = :tier;
+            ",
+        )?;
+        let rows = stmt.query_map_named(
+            named_params! { ":name": name, ":tier": State::tier(tier) },
+            State::rating,
+        )?;
+        for row in rows {
+            let (system, rating) = row?;
+            player.ratings.insert(system, rating);
+        }
+        Ok(player)
+    }
 
-        match player {
-            Ok(p) => p,
-            _ => Player::new((&name).to_string(), Elo::new()),
+    /// Get everyone fighting under `name` in `tier`: usually just the one character, but there
+    /// may be a whole team of them. See `team::members`.
+    pub fn get_members(state: &Connection, name: &str, tier: Option<Tier>) -> Result<Vec<Player>> {
+        team::members(name)
+            .iter()
+            .map(|member| State::get_player(state, member, tier))
+            .collect()
+    }
+
+    /// Get every player we know of, with their `Stats` and their ratings in `tier`. Unlike
+    /// `get_player`, there's no falling back to untiered ratings: players who haven't been rated
+    /// in the tier come back without any.
+    pub fn get_players(state: &Connection, tier: Option<Tier>) -> Result<Vec<Player>> {
+        let mut players: Vec<Player> = Vec::new();
+        let mut stmt = state.prepare(
+            "
+            SELECT players.name, ratings.system, ratings.rating, ratings.deviation, ratings.volatility,
+                   ratings.matches, players.matches, players.wins, players.losses, players.draws,
+                   players.first_seen, players.last_seen, players.streak
+            FROM players
+            LEFT JOIN ratings ON ratings.player = players.id AND ratings.tier = :tier
+            ORDER BY players.id;
+            ",
+        )?;
+        let mut rows = stmt.query_named(named_params! { ":tier": State::tier(tier) })?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            if players.last().map(|p| &p.name) != Some(&name) {
+                let mut player = Player::new(name);
+                player.tier = tier;
+                player.stats = State::stats(row, 6)?;
+                players.push(player);
+            }
+            if let Some(system) = row.get::<_, Option<String>>(1)? {
+                let rating = Rating {
+                    rating: row.get(2)?,
+                    deviation: row.get(3)?,
+                    volatility: row.get(4)?,
+                    matches: row.get(5)?,
+                };
+                let player = players.last_mut().expect("a player was just pushed");
+                player.ratings.insert(system, rating);
+            }
         }
+        Ok(players)
+    }
+
+    /// Read a `(system, Rating)` pair out of a row of the `ratings` table.
+    fn rating(row: &rusqlite::Row) -> rusqlite::Result<(String, Rating)> {
+        Ok((
+            row.get(0)?,
+            Rating {
+                rating: row.get(1)?,
+                deviation: row.get(2)?,
+                volatility: row.get(3)?,
+                matches: row.get(4)?,
+            },
+        ))
+    }
+
+    /// Read `Stats` out of the columns of a row of the `players` table, starting at `start`.
+    fn stats(row: &rusqlite::Row, start: usize) -> rusqlite::Result<Stats> {
+        Ok(Stats {
+            matches: row.get(start)?,
+            wins: row.get(start + 1)?,
+            losses: row.get(start + 2)?,
+            draws: row.get(start + 3)?,
+            first_seen: row.get(start + 4)?,
+            last_seen: row.get(start + 5)?,
+            streak: row.get(start + 6)?,
+        })
     }
 
-    /// Upsert information about a `Player`.
-    pub fn put_player(state: &Connection, player: &Player) {
-        let player = state.prepare(
-            "INSERT INTO players (name, elo) VALUES (:name, :elo) ON CONFLICT (name) DO UPDATE SET elo = :elo;"
-        ).and_then(|mut stmt| {
-            trace!("Updating player {} -- new elo: {}", player.name, player.elo.rating);
+    /// Get the specified player's `Stats`, which are empty if we've never seen them.
+    pub fn get_stats(state: &Connection, name: &str) -> Result<Stats> {
+        let stats = state
+            .query_row_named(
+                "
+                SELECT matches, wins, losses, draws, first_seen, last_seen, streak
+                FROM players WHERE name = :name;
+                ",
+                named_params! { ":name": name },
+                |row| State::stats(row, 0),
+            )
+            .optional()?;
+        Ok(stats.unwrap_or_default())
+    }
+
+    /// How `one` has done against `two`, whichever side each of them fought on. Names have to
+    /// match exactly, so a team's record is its own, not its members'.
+    pub fn get_head_to_head(state: &Connection, one: &str, two: &str) -> Result<HeadToHead> {
+        let mut stmt = state.prepare_cached(
+            "
+            SELECT fights.winner, fights.one = one.id
+            FROM players AS one, players AS two
+            JOIN fights ON (fights.one = one.id AND fights.two = two.id)
+                        OR (fights.one = two.id AND fights.two = one.id)
+            WHERE one.name = :one AND two.name = :two;
+            ",
+        )?;
+        let rows = stmt.query_map_named(named_params! { ":one": one, ":two": two }, |row| {
+            let winner: Winner = row.get(0)?;
+            let side = match row.get(1)? {
+                true => Winner::One,
+                false => Winner::Two,
+            };
+            Ok((side, winner))
+        })?;
+
+        let mut record = HeadToHead::default();
+        for row in rows {
+            let (side, winner) = row?;
+            record.record(side, winner);
+        }
+        Ok(record)
+    }
+
+    /// Replace a player's `Stats`. Players we haven't saved are left alone.
+    pub fn put_stats(state: &Connection, name: &str, stats: &Stats) -> Result<()> {
+        state.execute_named(
+            "
+            UPDATE players SET
+                matches = :matches,
+                wins = :wins,
+                losses = :losses,
+                draws = :draws,
+                first_seen = :first_seen,
+                last_seen = :last_seen,
+                streak = :streak
+            WHERE name = :name;
+            ",
+            named_params! {
+                ":name": name,
+                ":matches": stats.matches,
+                ":wins": stats.wins,
+                ":losses": stats.losses,
+                ":draws": stats.draws,
+                ":first_seen": stats.first_seen,
+                ":last_seen": stats.last_seen,
+                ":streak": stats.streak,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// How a tier is stored in the database. Untiered ratings use an empty string so they can
+    /// be part of the primary key.
+    fn tier(tier: Option<Tier>) -> String {
+        tier.map(|tier| tier.to_string()).unwrap_or_default()
+    }
+
+    /// Upsert information about a `Player`, along with their rating in every system for the
+    /// player's tier.
+    pub fn put_player(state: &Connection, player: &Player) -> Result<()> {
+        state.execute_named(
+            "INSERT INTO players (name) VALUES (:name) ON CONFLICT (name) DO NOTHING;",
+            named_params! { ":name": player.name },
+        )?;
+        let mut stmt = state.prepare(
+            "
+            INSERT INTO ratings (player, system, tier, rating, deviation, volatility, matches)
+                SELECT id, :system, :tier, :rating, :deviation, :volatility, :matches
+                FROM players WHERE name = :name
+                ON CONFLICT (player, system, tier) DO UPDATE SET
+                    rating = :rating,
+                    deviation = :deviation,
+                    volatility = :volatility,
+                    matches = :matches;
+            ",
+        )?;
+        for (system, rating) in &player.ratings {
+            trace!(
+                "Updating player {} -- new {}: {:.0}",
+                player.name,
+                system,
+                rating.rating
+            );
             stmt.execute_named(named_params! {
                 ":name": player.name,
-                ":elo": player.elo.rating,
-            })
-        });
+                ":system": system,
+                ":tier": State::tier(player.tier),
+                ":rating": rating.rating,
+                ":deviation": rating.deviation,
+                ":volatility": rating.volatility,
+                ":matches": rating.matches,
+            })?;
+        }
+        Ok(())
+    }
 
-        if let Err(error) = player {
-            warn!("Could not save player: {:?}", error);
+    /// Record who fought under a team's name. The team and any members we haven't seen before
+    /// are added as players, but none of them are given ratings here.
+    pub fn put_team(state: &Connection, team: &str, members: &[String]) -> Result<()> {
+        let mut stmt = state
+            .prepare("INSERT INTO players (name) VALUES (:name) ON CONFLICT (name) DO NOTHING;")?;
+        stmt.execute_named(named_params! { ":name": team })?;
+        for member in members {
+            stmt.execute_named(named_params! { ":name": member })?;
         }
+
+        let mut stmt = state.prepare(
+            "
+            INSERT INTO team_members (team, member)
+                SELECT team.id, member.id
+                FROM players AS team, players AS member
+                WHERE team.name = :team AND member.name = :member
+                ON CONFLICT (team, member) DO NOTHING;
+            ",
+        )?;
+        for member in members {
+            trace!("Recording team {} -- member: {}", team, member);
+            stmt.execute_named(named_params! { ":team": team, ":member": member })?;
+        }
+        Ok(())
     }
 
-    /// Add fight information.
-    pub fn put_event(state: &Connection, event: &Event) {
-        if let Event::Decided(winner, player_one, player_two) = event {
-            let fight = state.prepare(
+    /// Add fight information, including the pot from when betting was locked, the mode, and the
+    /// tier if we know it, and count the fight in the `Stats` of everyone who fought. Returns the
+    /// id of the recorded fight, or `None` if the event wasn't the end of a fight. Both players
+    /// have to have been saved already, or we get `Error::UnknownPlayers`.
+    pub fn put_event(state: &Connection, event: &Event) -> Result<Option<i64>> {
+        if let Event::Decided(winner, fight) = event {
+            let ended: String =
+                state.query_row("SELECT datetime('now');", rusqlite::NO_PARAMS, |row| {
+                    row.get(0)
+                })?;
+            let mut stmt = state.prepare(
                 "
                 WITH
                     player_one(id) AS (SELECT id FROM players WHERE name = :one),
                     player_two(id) AS (SELECT id FROM players WHERE name = :two)
-                INSERT INTO fights (ended, winner, one, two)
-                    SELECT datetime('now'), :winner, player_one.id, player_two.id FROM player_one, player_two;
+                INSERT INTO fights (ended, winner, one, two, one_total, two_total, tier, mode)
+                    SELECT :ended, :winner, player_one.id, player_two.id, :one_total, :two_total, :tier, :mode
+                    FROM player_one, player_two;
                 "
-            ).and_then(|mut stmt| {
-                trace!("Recording fight -- winner: {}, one: {}, two: {}", winner, player_one, player_two);
-                stmt.execute_named(named_params! {
-                    ":winner": winner,
-                    ":one": player_one,
-                    ":two": player_two,
-                })
-            });
+            )?;
+            trace!(
+                "Recording fight -- winner: {}, one: {}, two: {}",
+                winner,
+                fight.one,
+                fight.two
+            );
+            let inserted = stmt.execute_named(named_params! {
+                ":ended": ended,
+                ":winner": winner,
+                ":one": fight.one,
+                ":two": fight.two,
+                ":one_total": fight.pot.one as i64,
+                ":two_total": fight.pot.two as i64,
+                ":tier": fight.tier.map(|tier| tier.to_string()),
+                ":mode": fight.mode.to_string(),
+            })?;
+
+            // Nothing is inserted when either player is missing.
+            if inserted == 0 {
+                let mut missing = Vec::new();
+                for name in &[&fight.one, &fight.two] {
+                    let id: Option<i64> = state
+                        .query_row_named(
+                            "SELECT id FROM players WHERE name = :name;",
+                            named_params! { ":name": name },
+                            |row| row.get(0),
+                        )
+                        .optional()?;
+                    if id.is_none() {
+                        missing.push(name.to_string());
+                    }
+                }
+                return Err(Error::UnknownPlayers(missing));
+            }
+            let id = state.last_insert_rowid();
+
+            for (side, name) in &[(Winner::One, &fight.one), (Winner::Two, &fight.two)] {
+                for member in team::members(name) {
+                    let mut stats = State::get_stats(state, &member)?;
+                    stats.record(*side, *winner, &ended);
+                    State::put_stats(state, &member, &stats)?;
+                }
+            }
+            return Ok(Some(id));
+        }
+
+        Ok(None)
+    }
 
-            if let Err(error) = fight {
-                warn!("Could not save fight: {:?}", error);
+    /// Every fight we have a record of, oldest first.
+    pub fn get_fights(state: &Connection) -> Result<Vec<Fight>> {
+        let mut stmt = state.prepare(
+            "
+            SELECT fights.id, fights.ended, fights.winner, one.name, two.name,
+                   fights.one_total, fights.two_total, fights.tier, fights.mode
+            FROM fights
+            JOIN players AS one ON one.id = fights.one
+            JOIN players AS two ON two.id = fights.two
+            ORDER BY fights.ended, fights.id;
+            ",
+        )?;
+
+        let fights = stmt.query_map(rusqlite::NO_PARAMS, |row| {
+            let one_total: Option<i64> = row.get(5)?;
+            let two_total: Option<i64> = row.get(6)?;
+            Ok(Fight {
+                id: row.get(0)?,
+                ended: row.get(1)?,
+                winner: row.get(2)?,
+                one: row.get(3)?,
+                two: row.get(4)?,
+                pot: match (one_total, two_total) {
+                    (Some(one), Some(two)) => Some(Pot {
+                        one: one as u64,
+                        two: two as u64,
+                    }),
+                    _ => None,
+                },
+                tier: row
+                    .get::<_, Option<String>>(7)?
+                    .and_then(|tier| tier.parse().ok()),
+                mode: row
+                    .get::<_, Option<String>>(8)?
+                    .and_then(|mode| mode.parse().ok()),
+            })
+        })?;
+
+        Ok(fights.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Add a fight recorded somewhere else, along with any players we haven't seen before.
+    /// Nobody's ratings change. Returns `false` if we already had the fight.
+    pub fn put_fight(state: &Connection, fight: &Fight) -> Result<bool> {
+        let mut stmt = state
+            .prepare("INSERT INTO players (name) VALUES (:name) ON CONFLICT (name) DO NOTHING;")?;
+        stmt.execute_named(named_params! { ":name": fight.one })?;
+        stmt.execute_named(named_params! { ":name": fight.two })?;
+
+        let existing: i64 = state.query_row_named(
+            "
+            SELECT COUNT(*)
+            FROM fights
+            JOIN players AS one ON one.id = fights.one
+            JOIN players AS two ON two.id = fights.two
+            WHERE fights.ended = :ended AND one.name = :one AND two.name = :two;
+            ",
+            named_params! { ":ended": fight.ended, ":one": fight.one, ":two": fight.two },
+            |row| row.get(0),
+        )?;
+        if existing > 0 {
+            return Ok(false);
+        }
+
+        trace!(
+            "Importing fight -- ended: {}, one: {}, two: {}",
+            fight.ended,
+            fight.one,
+            fight.two
+        );
+        state.execute_named(
+            "
+            INSERT INTO fights (ended, winner, one, two, one_total, two_total, tier, mode)
+                SELECT :ended, :winner, one.id, two.id, :one_total, :two_total, :tier, :mode
+                FROM players AS one, players AS two
+                WHERE one.name = :one AND two.name = :two;
+            ",
+            named_params! {
+                ":ended": fight.ended,
+                ":winner": fight.winner,
+                ":one": fight.one,
+                ":two": fight.two,
+                ":one_total": fight.pot.map(|pot| pot.one as i64),
+                ":two_total": fight.pot.map(|pot| pot.two as i64),
+                ":tier": fight.tier.map(|tier| tier.to_string()),
+                ":mode": fight.mode.map(|mode| mode.to_string()),
+            },
+        )?;
+        Ok(true)
+    }
+
+    /// Record a `Bet` we tried to place. Returns the id of the bet so it can be settled once the
+    /// fight is over.
+    pub fn put_bet(state: &Connection, bet: &Bet) -> Result<i64> {
+        let mut stmt = state.prepare(
+            "
+            INSERT INTO bets (placed, side, wager, balance_before, probability, strategy, success, simulated)
+                VALUES (datetime('now'), :side, :wager, :balance_before, :probability, :strategy, :success, :simulated);
+            "
+        )?;
+        trace!("Recording bet -- side: {}, wager: {}", bet.side, bet.wager);
+        stmt.execute_named(named_params! {
+            ":side": bet.side,
+            ":wager": bet.wager,
+            ":balance_before": bet.balance_before,
+            ":probability": f64::from(bet.probability),
+            ":strategy": bet.strategy,
+            ":success": bet.success,
+            ":simulated": bet.simulated,
+        })?;
+        Ok(state.last_insert_rowid())
+    }
+
+    /// Tie a bet to the fight it was placed on, along with our balance after the payout.
+    pub fn settle_bet(state: &Connection, bet: i64, fight: i64, balance_after: u32) -> Result<()> {
+        trace!(
+            "Settling bet {} -- fight: {}, balance: {}",
+            bet,
+            fight,
+            balance_after
+        );
+        state.execute_named(
+            "UPDATE bets SET fight = :fight, balance_after = :balance_after WHERE id = :id;",
+            named_params! {
+                ":id": bet,
+                ":fight": fight,
+                ":balance_after": balance_after,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Record how a fight changed the ratings of everyone who fought on `side`, given who they
+    /// were `before` and `after` it, in the same order.
+    pub fn put_history(
+        state: &Connection,
+        fight: i64,
+        side: Winner,
+        systems: &[Box<dyn RatingSystem>],
+        before: &[Player],
+        after: &[Player],
+    ) -> Result<()> {
+        let mut stmt = state.prepare_cached(
+            "
+            INSERT INTO rating_history (fight, player, system, side, rating_before, rating_after)
+                SELECT :fight, id, :system, :side, :before, :after
+                FROM players WHERE name = :name
+                ON CONFLICT (fight, player, system) DO UPDATE SET
+                    side = :side,
+                    rating_before = :before,
+                    rating_after = :after;
+            ",
+        )?;
+        for (before, after) in before.iter().zip(after) {
+            for system in systems {
+                stmt.execute_named(named_params! {
+                    ":fight": fight,
+                    ":name": after.name,
+                    ":system": system.name(),
+                    ":side": side,
+                    ":before": before.rating(system.as_ref()).rating,
+                    ":after": after.rating(system.as_ref()).rating,
+                })?;
             }
         }
+        Ok(())
+    }
+
+    /// The `limit` most recent changes to a player's rating in `system`, oldest first.
+    pub fn get_history(
+        state: &Connection,
+        name: &str,
+        system: &str,
+        limit: usize,
+    ) -> Result<Vec<Snapshot>> {
+        let mut stmt = state.prepare(
+            "
+            SELECT fights.id, fights.ended, fights.tier, history.side, fights.winner,
+                   CASE history.side WHEN 1 THEN two.name ELSE one.name END,
+                   history.rating_before, history.rating_after
+            FROM rating_history AS history
+            JOIN players AS player ON player.id = history.player
+            JOIN fights ON fights.id = history.fight
+            JOIN players AS one ON one.id = fights.one
+            JOIN players AS two ON two.id = fights.two
+            WHERE player.name = :name AND history.system = :system
+            ORDER BY fights.ended DESC, fights.id DESC
+            LIMIT :limit;
+            ",
+        )?;
+        let rows = stmt.query_map_named(
+            named_params! { ":name": name, ":system": system, ":limit": limit as i64 },
+            |row| {
+                Ok(Snapshot {
+                    fight: row.get(0)?,
+                    ended: row.get(1)?,
+                    tier: row
+                        .get::<_, Option<String>>(2)?
+                        .and_then(|tier| tier.parse().ok()),
+                    side: row.get(3)?,
+                    winner: row.get(4)?,
+                    opponent: row.get(5)?,
+                    before: row.get(6)?,
+                    after: row.get(7)?,
+                })
+            },
+        )?;
+        let mut history = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        history.reverse();
+        Ok(history)
+    }
+
+    /// Look the database over for problems.
+    pub fn check(state: &Connection) -> Result<Health> {
+        let mut stmt = state.prepare("PRAGMA integrity_check;")?;
+        let integrity = stmt
+            .query_map(rusqlite::NO_PARAMS, |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        let count = |sql: &str| -> Result<i64> {
+            Ok(state.query_row(sql, rusqlite::NO_PARAMS, |row| row.get(0))?)
+        };
+
+        Ok(Health {
+            integrity,
+            version: count("PRAGMA user_version;")? as usize,
+            latest: MIGRATIONS.len(),
+            players: count("SELECT COUNT(*) FROM players;")?,
+            fights: count("SELECT COUNT(*) FROM fights;")?,
+            bets: count("SELECT COUNT(*) FROM bets;")?,
+            orphaned_fights: count(
+                "
+                SELECT COUNT(*) FROM fights
+                WHERE one NOT IN (SELECT id FROM players) OR two NOT IN (SELECT id FROM players);
+                ",
+            )?,
+            unsettled_bets: count("SELECT COUNT(*) FROM bets WHERE fight IS NULL;")?,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::elo::Winner;
     use super::*;
+    use crate::elo::Elo;
+    use crate::game::{Match, Mode};
+    use crate::glicko::Glicko;
+    use crate::mock;
+    use crate::rating::{EloSystem, GlickoSystem};
+
+    #[test]
+    fn test_put_get_player() -> Result<()> {
+        let state = mock::database()?;
+        let mut player_orig = Player::new(String::from("test"));
+        player_orig.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1337)));
+        player_orig.set_rating(
+            &GlickoSystem {},
+            Rating::from(Glicko::with_rating(1600f64, 80f64, 0.05f64)),
+        );
+
+        State::put_player(&state, &player_orig)?;
+        let player_saved = State::get_player(&state, &String::from("test"), None)?;
+
+        assert_eq!(player_orig.ratings, player_saved.ratings);
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_get_tiered_player() -> Result<()> {
+        let state = mock::database()?;
+        let mut untiered = Player::new(String::from("test"));
+        untiered.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1200)));
+        untiered.set_rating(&GlickoSystem {}, Rating::from(Glicko::new()));
+        State::put_player(&state, &untiered)?;
+
+        // New to the tier, so we start from the untiered ratings.
+        let mut tiered = State::get_player(&state, &String::from("test"), Some(Tier::A))?;
+        assert_eq!(tiered.ratings, untiered.ratings);
+
+        tiered.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(900)));
+        State::put_player(&state, &tiered)?;
+        let saved = State::get_player(&state, &String::from("test"), Some(Tier::A))?;
+        assert_eq!(saved.ratings, tiered.ratings);
+        let saved = State::get_player(&state, &String::from("test"), None)?;
+        assert_eq!(saved.ratings, untiered.ratings);
+        assert_eq!(
+            State::get_players(&state, None)?[0].ratings,
+            untiered.ratings
+        );
+        assert_eq!(
+            State::get_players(&state, Some(Tier::A))?[0].ratings,
+            tiered.ratings
+        );
+        assert!(State::get_players(&state, Some(Tier::B))?[0]
+            .ratings
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_players() -> Result<()> {
+        let state = mock::database()?;
+        let mut rated = Player::new(String::from("rated"));
+        rated.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1100)));
+        State::put_player(&state, &Player::new(String::from("unrated")))?;
+        State::put_player(&state, &rated)?;
+
+        let players = State::get_players(&state, None)?;
+        assert_eq!(players.len(), 2);
+        assert_eq!(players[0].name, "unrated");
+        assert!(players[0].ratings.is_empty());
+        assert_eq!(players[1].ratings, rated.ratings);
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_event_fail_no_player() -> Result<()> {
+        let state = mock::database()?;
+        let event = Event::Decided(
+            Winner::One,
+            Match {
+                one: String::from("one"),
+                two: String::from("two"),
+                ..Default::default()
+            },
+        );
+        State::put_player(&state, &Player::new(String::from("two")))?;
+        match State::put_event(&state, &event) {
+            Err(Error::UnknownPlayers(names)) => assert_eq!(names, vec![String::from("one")]),
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_team() -> Result<()> {
+        let state = mock::database()?;
+        let members = vec![String::from("Ryu"), String::from("Ken")];
+        State::put_team(&state, "Ryu / Ken", &members)?;
+        State::put_team(&state, "Ryu / Ken", &members)?;
+
+        let count: i64 = state.query_row(
+            "
+            SELECT COUNT(*) FROM team_members
+            JOIN players ON players.id = team_members.team
+            WHERE players.name = 'Ryu / Ken';
+            ",
+            rusqlite::NO_PARAMS,
+            |row| row.get(0),
+        )?;
+        assert_eq!(count, 2);
+        assert_eq!(State::get_players(&state, None)?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_event_with_pot() -> Result<()> {
+        let state = mock::database()?;
+        State::put_player(&state, &Player::new(String::from("one")))?;
+        State::put_player(&state, &Player::new(String::from("two")))?;
+        let pot = Pot { one: 100, two: 250 };
+        let event = Event::Decided(
+            Winner::Two,
+            Match {
+                one: String::from("one"),
+                two: String::from("two"),
+                pot,
+                ..Default::default()
+            },
+        );
+        State::put_event(&state, &event)?;
+
+        let totals: (i64, i64) = state.query_row(
+            "SELECT one_total, two_total FROM fights;",
+            rusqlite::NO_PARAMS,
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert_eq!(totals, (100, 250));
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_event_counts_stats() -> Result<()> {
+        let state = mock::database()?;
+        for name in &["Ryu", "Ken", "Akuma"] {
+            State::put_player(&state, &Player::new(String::from(*name)))?;
+        }
+        State::put_team(
+            &state,
+            "Ryu / Ken",
+            &[String::from("Ryu"), String::from("Ken")],
+        )?;
+        for (winner, one) in &[(Winner::One, "Ryu"), (Winner::Two, "Ryu / Ken")] {
+            let event = Event::Decided(
+                *winner,
+                Match {
+                    one: String::from(*one),
+                    two: String::from("Akuma"),
+                    ..Default::default()
+                },
+            );
+            State::put_event(&state, &event)?;
+        }
+
+        let ryu = State::get_player(&state, &String::from("Ryu"), None)?.stats;
+        assert_eq!(
+            (ryu.matches, ryu.wins, ryu.losses, ryu.streak),
+            (2, 1, 1, -1)
+        );
+        assert!(ryu.first_seen.is_some());
+        let ken = State::get_stats(&state, "Ken")?;
+        assert_eq!((ken.matches, ken.losses), (1, 1));
+        // The team's members fought, not the team.
+        assert_eq!(State::get_stats(&state, "Ryu / Ken")?, Stats::default());
+        let players = State::get_players(&state, None)?;
+        let akuma = players.iter().find(|player| player.name == "Akuma");
+        assert_eq!(akuma.map(|player| player.stats.streak), Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_head_to_head() -> Result<()> {
+        let state = mock::database()?;
+        for name in &["Ryu", "Ken", "Akuma"] {
+            State::put_player(&state, &Player::new(String::from(*name)))?;
+        }
+        for (winner, one, two) in &[
+            (Winner::One, "Ryu", "Ken"),
+            (Winner::One, "Ken", "Ryu"),
+            (Winner::Two, "Ken", "Ryu"),
+            (Winner::Draw, "Ryu", "Ken"),
+            (Winner::One, "Ryu", "Akuma"),
+        ] {
+            let event = Event::Decided(
+                *winner,
+                Match {
+                    one: String::from(*one),
+                    two: String::from(*two),
+                    ..Default::default()
+                },
+            );
+            State::put_event(&state, &event)?;
+        }
+
+        let record = State::get_head_to_head(&state, "Ryu", "Ken")?;
+        assert_eq!(
+            record,
+            HeadToHead {
+                wins: 2,
+                losses: 1,
+                draws: 1,
+            }
+        );
+        assert_eq!(
+            State::get_head_to_head(&state, "Ken", "Ryu")?,
+            record.reversed()
+        );
+        assert_eq!(
+            State::get_head_to_head(&state, "Ken", "Akuma")?,
+            HeadToHead::default()
+        );
+        assert_eq!(
+            State::get_head_to_head(&state, "Ryu", "Nobody")?,
+            HeadToHead::default()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_is_idempotent() -> Result<()> {
+        let state = Connection::open_in_memory()?;
+        state.execute_batch(include_str!("schema.sql"))?;
+        State::migrate(&state)?;
+        State::migrate(&state)?;
+
+        let version: i64 = state.query_row("PRAGMA user_version;", rusqlite::NO_PARAMS, |row| {
+            row.get(0)
+        })?;
+        assert_eq!(version as usize, MIGRATIONS.len());
+        Ok(())
+    }
 
     #[test]
-    fn test_put_get_player() -> result::Result<(), Box<dyn Error>> {
-        let mut config: Config = Default::default();
-        config.file_db = String::from("memory");
+    fn test_migrate_moves_elo_into_ratings() -> Result<()> {
+        let state = Connection::open_in_memory()?;
+        state.execute_batch(include_str!("schema.sql"))?;
+        state.execute_batch("INSERT INTO players (id, name, elo) VALUES (7, 'Ryu', 1234);")?;
+        State::migrate(&state)?;
+
+        let player = State::get_player(&state, &String::from("Ryu"), None)?;
+        assert_eq!(player.rating(&EloSystem::default()).rating, 1234f64);
+        // The old column is gone.
+        assert!(state.prepare("SELECT elo FROM players;").is_err());
+        Ok(())
+    }
 
-        let state = State::new(&config)?;
-        let player_orig = Player::new(String::from("test"), Elo::with_rating(1337));
+    #[test]
+    fn test_put_settle_bet() -> Result<()> {
+        let state = mock::database()?;
+        State::put_player(&state, &Player::new(String::from("one")))?;
+        State::put_player(&state, &Player::new(String::from("two")))?;
 
-        State::put_player(&state, &player_orig);
-        let player_saved = State::get_player(&state, &String::from("test"));
+        let bet = Bet {
+            side: Winner::One,
+            wager: 420,
+            balance_before: 1000,
+            probability: 0.75f32,
+            strategy: String::from("favourite"),
+            success: true,
+            simulated: false,
+        };
+        let bet_id = State::put_bet(&state, &bet)?;
+        let event = Event::Decided(
+            Winner::One,
+            Match {
+                one: String::from("one"),
+                two: String::from("two"),
+                ..Default::default()
+            },
+        );
+        let fight_id = State::put_event(&state, &event)?.expect("fight was not saved");
+        State::settle_bet(&state, bet_id, fight_id, 1420)?;
 
-        assert_eq!(player_orig.elo.rating, player_saved.elo.rating);
+        let settled: (i64, u32) = state.query_row(
+            "SELECT fight, balance_after FROM bets WHERE id = ?;",
+            [bet_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert_eq!(settled, (fight_id, 1420));
         Ok(())
     }
 
     #[test]
-    fn test_put_event_fail_no_player() -> result::Result<(), Box<dyn Error>> {
-        let mut config: Config = Default::default();
-        config.file_db = String::from("memory");
+    fn test_get_fights() -> Result<()> {
+        let state = mock::database()?;
+        State::put_player(&state, &Player::new(String::from("one")))?;
+        State::put_player(&state, &Player::new(String::from("two")))?;
+        let pot = Pot { one: 10, two: 20 };
+        State::put_event(
+            &state,
+            &Event::Decided(
+                Winner::One,
+                Match {
+                    one: String::from("one"),
+                    two: String::from("two"),
+                    pot,
+                    tier: Some(Tier::P),
+                    mode: Mode::Tournament,
+                },
+            ),
+        )?;
+        state.execute(
+            "INSERT INTO fights (ended, winner, one, two) VALUES ('2021-01-01 00:00:00', 2, 2, 1);",
+            rusqlite::NO_PARAMS,
+        )?;
+
+        let fights = State::get_fights(&state)?;
+        assert_eq!(fights.len(), 2);
+        assert_eq!(fights[0].winner, Winner::Two);
+        assert_eq!(fights[0].one, "two");
+        assert_eq!(fights[0].pot, None);
+        assert_eq!(fights[0].tier, None);
+        assert_eq!(fights[1].pot, Some(pot));
+        assert_eq!(fights[1].tier, Some(Tier::P));
+        assert_eq!(fights[0].mode, None);
+        assert_eq!(fights[1].mode, Some(Mode::Tournament));
 
-        let state = State::new(&config)?;
-        let event = Event::Decided(Winner::One, String::from("one"), String::from("two"));
-        State::put_event(&state, &event);
+        // A winner we don't know how to read is an error, not a draw.
+        state.execute(
+            "UPDATE fights SET winner = 4 WHERE id = 1;",
+            rusqlite::NO_PARAMS,
+        )?;
+        assert!(State::get_fights(&state).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_fight() -> Result<()> {
+        let state = mock::database()?;
+        let fight = Fight {
+            id: 0,
+            ended: String::from("2021-01-01 00:00:00"),
+            winner: Winner::Two,
+            one: String::from("one"),
+            two: String::from("two"),
+            pot: Some(Pot { one: 10, two: 20 }),
+            tier: Some(Tier::S),
+            mode: Some(Mode::Matchmaking),
+        };
+        assert!(State::put_fight(&state, &fight)?);
+        assert!(!State::put_fight(&state, &fight)?);
+
+        let fights = State::get_fights(&state)?;
+        assert_eq!(fights.len(), 1);
+        assert_eq!(
+            fights[0],
+            Fight {
+                id: fights[0].id,
+                ..fight
+            }
+        );
+        assert!(State::get_players(&state, None)?
+            .iter()
+            .all(|player| player.ratings.is_empty()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check() -> Result<()> {
+        let state = mock::database()?;
+        State::put_player(&state, &Player::new(String::from("one")))?;
+        assert!(State::check(&state)?.is_ok());
 
+        state.execute(
+            "INSERT INTO fights (ended, winner, one, two) VALUES ('2021-01-01 00:00:00', 1, 1, 42);",
+            rusqlite::NO_PARAMS,
+        )?;
+        let health = State::check(&state)?;
+        assert_eq!(health.players, 1);
+        assert_eq!(health.fights, 1);
+        assert_eq!(health.orphaned_fights, 1);
+        assert!(!health.is_ok());
         Ok(())
     }
 }
@@ -0,0 +1,532 @@
+use log::info;
+
+use crate::config::Config;
+use crate::elo::Winner;
+use crate::fight::HeadToHead;
+use crate::game::{Mode, Pot};
+use crate::player::Player;
+use crate::rating::{self, RatingSystem};
+
+/// What we know about the current match and our account when bets open.
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    pub balance: u32,
+    pub pot: Pot,
+    pub mode: Mode,
+    /// How the two sides have done against each other before.
+    pub head_to_head: HeadToHead,
+}
+
+/// What a `Strategy` wants us to do for a match.
+#[derive(Debug, PartialEq)]
+pub enum Decision {
+    /// Bet `wager` on `winner`, whom we think wins with the given `probability`.
+    Bet {
+        winner: Winner,
+        wager: u32,
+        probability: f32,
+    },
+    /// Sit this match out. The reason is logged.
+    Skip(String),
+}
+
+/// Decides which side to bet on and how much to wager.
+pub trait Strategy {
+    /// The name used to select the strategy in the configuration.
+    fn name(&self) -> &'static str;
+
+    /// Look at both players and the match context, and decide what to do.
+    fn decide(&self, one: &Player, two: &Player, context: &Context) -> Decision;
+}
+
+/// How much of our balance we put on a bet once we've picked a side.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sizing {
+    /// 10% of our balance, or 420 if we're close to broke.
+    Flat,
+    /// A fraction of the Kelly bet for our win probability and the pot odds, kept between
+    /// `floor` and `ceiling`, or nothing at all when Kelly says not to bet. When the pot is still
+    /// empty we assume even odds, which in practice is always: we bet as soon as betting opens,
+    /// before SaltyBet shows any totals, and backtests only show the strategy an empty pot.
+    Kelly {
+        fraction: f32,
+        floor: u32,
+        ceiling: Option<u32>,
+    },
+    /// Everything we have. Tournament balances reset every tournament, so there's little
+    /// point in holding back.
+    AllIn,
+}
+
+impl Sizing {
+    /// How much to bet given the `probability` we think our side wins and the net `odds` we'd
+    /// be paid if it does.
+    pub fn wager(&self, probability: f32, odds: Option<f32>, balance: u32) -> u32 {
+        match self {
+            Sizing::Flat => {
+                if balance >= 4_200 {
+                    balance / 10
+                } else {
+                    420u32
+                }
+            }
+            Sizing::Kelly {
+                fraction,
+                floor,
+                ceiling,
+            } => {
+                // Kelly: f = p - (1 - p) / b, where `b` is the net odds. Without an edge any
+                // bet loses money in the long run, so the floor only applies to real bets.
+                let odds = odds.unwrap_or(1f32);
+                let kelly = probability - (1f32 - probability) / odds;
+                if kelly <= 0f32 {
+                    return 0;
+                }
+                let wager = (balance as f32 * kelly * fraction).floor() as u32;
+                let wager = wager.max(*floor);
+                let wager = match ceiling {
+                    Some(ceiling) => wager.min(*ceiling),
+                    None => wager,
+                };
+                wager.min(balance)
+            }
+            Sizing::AllIn => balance,
+        }
+    }
+}
+
+/// What to do about a match we don't know enough about to trust our prediction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unsure {
+    /// Sit it out.
+    Skip,
+    /// Bet the given amount, or everything we have if that's less.
+    Minimum(u32),
+}
+
+/// When to stop trusting a prediction: when either side has fewer than `min_fights` recorded
+/// fights in their `Stats`, or when our chance of winning is less than `min_edge` better than a
+/// coin flip. The default trusts everything.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caution {
+    pub min_fights: u32,
+    pub min_edge: f32,
+    pub unsure: Unsure,
+}
+
+impl Default for Caution {
+    fn default() -> Self {
+        Caution {
+            min_fights: 0,
+            min_edge: 0f32,
+            unsure: Unsure::Skip,
+        }
+    }
+}
+
+impl Caution {
+    /// Why we shouldn't trust a `probability` of winning a match between `one` and `two`, or
+    /// `None` if we can.
+    pub fn doubt(&self, one: &Player, two: &Player, probability: f32) -> Option<String> {
+        for player in &[one, two] {
+            let fights = player.stats.matches;
+            if fights < self.min_fights {
+                return Some(format!(
+                    "{} has {} recorded fights, fewer than {}",
+                    player.name, fights, self.min_fights
+                ));
+            }
+        }
+        let edge = probability - 0.5f32;
+        if edge < self.min_edge {
+            return Some(format!(
+                "an edge of {:.1}% is below {:.1}%",
+                edge * 100f32,
+                self.min_edge * 100f32
+            ));
+        }
+        None
+    }
+}
+
+/// How much to trust two sides' record against each other over their ratings: once they've met
+/// at least `min_meetings` times, the chance we predict is moved `weight` of the way towards
+/// what their head-to-head record says. The default ignores head-to-head records.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Blend {
+    pub min_meetings: u32,
+    pub weight: f32,
+}
+
+impl Blend {
+    /// Blend the rated `probability` of the first side winning with their `record` against the
+    /// second.
+    pub fn apply(&self, probability: f32, record: &HeadToHead) -> f32 {
+        if self.weight == 0f32 || record.meetings() < self.min_meetings {
+            probability
+        } else {
+            probability * (1f32 - self.weight) + record.chance() * self.weight
+        }
+    }
+}
+
+impl From<&Config> for Blend {
+    fn from(config: &Config) -> Self {
+        Blend {
+            min_meetings: config.head_to_head_min,
+            weight: config.head_to_head_weight,
+        }
+    }
+}
+
+/// Always bets on the player with the higher rating (player one on a tie), taking their
+/// head-to-head record into account.
+pub struct Favourite {
+    pub rating: Box<dyn RatingSystem>,
+    pub sizing: Sizing,
+    /// How much to bet during tournaments.
+    pub tournament: Sizing,
+    /// What to do when we don't trust the ratings.
+    pub caution: Caution,
+    pub head_to_head: Blend,
+}
+
+impl Strategy for Favourite {
+    fn name(&self) -> &'static str {
+        "favourite"
+    }
+
+    fn decide(&self, one: &Player, two: &Player, context: &Context) -> Decision {
+        let expected = self.rating.expected(one, two);
+        let expected = self.head_to_head.apply(expected, &context.head_to_head);
+        let (winner, probability) = if expected >= 0.5f32 {
+            (Winner::One, expected)
+        } else {
+            (Winner::Two, 1f32 - expected)
+        };
+
+        let doubt = self.caution.doubt(one, two, probability);
+        let wager = match (doubt, self.caution.unsure) {
+            (Some(reason), Unsure::Skip) => return Decision::Skip(reason),
+            (Some(reason), Unsure::Minimum(minimum)) => {
+                info!(
+                    "Betting the minimum on {} vs {}: {}",
+                    one.name, two.name, reason
+                );
+                minimum.min(context.balance)
+            }
+            (None, _) => {
+                let sizing = match context.mode {
+                    Mode::Tournament => &self.tournament,
+                    _ => &self.sizing,
+                };
+                let odds = context.pot.odds(&winner);
+                match sizing.wager(probability, odds, context.balance) {
+                    0 => {
+                        return Decision::Skip(format!(
+                            "nothing worth betting at {:.1}%",
+                            probability * 100f32
+                        ))
+                    }
+                    wager => wager,
+                }
+            }
+        };
+        Decision::Bet {
+            winner,
+            wager,
+            probability,
+        }
+    }
+}
+
+/// Build the sizing mode called `name`, if it is one we know about. The rest of the settings
+/// come from the configuration.
+pub fn sizing_from_config(name: &str, config: &Config) -> Option<Sizing> {
+    match name {
+        "flat" => Some(Sizing::Flat),
+        "kelly" => Some(Sizing::Kelly {
+            fraction: config.kelly_fraction,
+            floor: config.wager_floor,
+            ceiling: config.wager_ceiling,
+        }),
+        "all-in" => Some(Sizing::AllIn),
+        _ => None,
+    }
+}
+
+/// Build the `Caution` described in the configuration, if `unsure_wager` is one we know about.
+pub fn caution_from_config(config: &Config) -> Option<Caution> {
+    let unsure = match config.unsure_wager.as_str() {
+        "skip" => Unsure::Skip,
+        "minimum" => Unsure::Minimum(config.wager_floor),
+        _ => return None,
+    };
+    Some(Caution {
+        min_fights: config.min_fights,
+        min_edge: config.min_edge,
+        unsure,
+    })
+}
+
+/// Build the strategy selected in the configuration, if it is one we know about.
+pub fn from_config(config: &Config) -> Option<Box<dyn Strategy>> {
+    let rating = rating::from_name(&config.rating, config)?;
+    let sizing = sizing_from_config(&config.wager, config)?;
+    let tournament = sizing_from_config(&config.tournament_wager, config)?;
+    let caution = caution_from_config(config)?;
+    match config.strategy.as_str() {
+        "favourite" => Some(Box::new(Favourite {
+            rating,
+            sizing,
+            tournament,
+            caution,
+            head_to_head: Blend::from(config),
+        })),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elo::Elo;
+    use crate::glicko::Glicko;
+    use crate::rating::{EloSystem, GlickoSystem, Rating};
+
+    #[test]
+    fn test_favourite_bets_on_higher_rating() {
+        let mut one = Player::new(String::from("one"));
+        let two = Player::new(String::from("two"));
+        one.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1200)));
+        let context = Context {
+            balance: 10_000,
+            ..Default::default()
+        };
+        let strategy = Favourite {
+            rating: Box::new(EloSystem::default()),
+            sizing: Sizing::Flat,
+            tournament: Sizing::AllIn,
+            caution: Caution::default(),
+            head_to_head: Blend::default(),
+        };
+        assert_eq!(
+            strategy.decide(&one, &two, &context),
+            Decision::Bet {
+                winner: Winner::One,
+                wager: 1_000,
+                probability: EloSystem::default().expected(&one, &two),
+            }
+        );
+    }
+
+    #[test]
+    fn test_favourite_with_glicko() {
+        let mut one = Player::new(String::from("one"));
+        let two = Player::new(String::from("two"));
+        one.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1200)));
+        one.set_rating(
+            &GlickoSystem {},
+            Rating::from(Glicko::with_rating(1400f64, 350f64, 0.06f64)),
+        );
+        let strategy = Favourite {
+            rating: Box::new(GlickoSystem {}),
+            sizing: Sizing::Flat,
+            tournament: Sizing::AllIn,
+            caution: Caution::default(),
+            head_to_head: Blend::default(),
+        };
+        match strategy.decide(&one, &two, &Default::default()) {
+            Decision::Bet { winner, .. } => assert_eq!(winner, Winner::Two),
+            decision => panic!("unexpected decision: {:?}", decision),
+        }
+    }
+
+    #[test]
+    fn test_favourite_all_in_during_tournaments() {
+        let one = Player::new(String::from("one"));
+        let two = Player::new(String::from("two"));
+        let strategy = Favourite {
+            rating: Box::new(EloSystem::default()),
+            sizing: Sizing::Flat,
+            tournament: Sizing::AllIn,
+            caution: Caution::default(),
+            head_to_head: Blend::default(),
+        };
+        let mut context = Context {
+            balance: 10_000,
+            ..Default::default()
+        };
+        let wager = |decision| match decision {
+            Decision::Bet { wager, .. } => wager,
+            decision => panic!("unexpected decision: {:?}", decision),
+        };
+        assert_eq!(wager(strategy.decide(&one, &two, &context)), 1_000);
+        context.mode = Mode::Tournament;
+        assert_eq!(wager(strategy.decide(&one, &two, &context)), 10_000);
+    }
+
+    #[test]
+    fn test_favourite_is_cautious_with_newcomers() {
+        let mut one = Player::new(String::from("one"));
+        let two = Player::new(String::from("two"));
+        one.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1200)));
+        one.stats.matches = 10;
+        let context = Context {
+            balance: 10_000,
+            ..Default::default()
+        };
+        let mut strategy = Favourite {
+            rating: Box::new(EloSystem::default()),
+            sizing: Sizing::Flat,
+            tournament: Sizing::AllIn,
+            caution: Caution {
+                min_fights: 5,
+                ..Default::default()
+            },
+            head_to_head: Blend::default(),
+        };
+        assert_eq!(
+            strategy.decide(&one, &two, &context),
+            Decision::Skip(String::from("two has 0 recorded fights, fewer than 5"))
+        );
+
+        strategy.caution.unsure = Unsure::Minimum(100);
+        match strategy.decide(&one, &two, &context) {
+            Decision::Bet { winner, wager, .. } => assert_eq!((winner, wager), (Winner::One, 100)),
+            decision => panic!("unexpected decision: {:?}", decision),
+        }
+    }
+
+    #[test]
+    fn test_favourite_skips_coin_flips() {
+        let one = Player::new(String::from("one"));
+        let two = Player::new(String::from("two"));
+        let strategy = Favourite {
+            rating: Box::new(EloSystem::default()),
+            sizing: Sizing::Flat,
+            tournament: Sizing::AllIn,
+            caution: Caution {
+                min_edge: 0.05f32,
+                ..Default::default()
+            },
+            head_to_head: Blend::default(),
+        };
+        match strategy.decide(&one, &two, &Default::default()) {
+            Decision::Skip(reason) => assert_eq!(reason, "an edge of 0.0% is below 5.0%"),
+            decision => panic!("unexpected decision: {:?}", decision),
+        }
+    }
+
+    #[test]
+    fn test_favourite_weighs_head_to_head() {
+        let mut one = Player::new(String::from("one"));
+        let two = Player::new(String::from("two"));
+        one.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1100)));
+        let mut context = Context {
+            balance: 10_000,
+            head_to_head: HeadToHead {
+                wins: 0,
+                losses: 4,
+                draws: 0,
+            },
+            ..Default::default()
+        };
+        let strategy = Favourite {
+            rating: Box::new(EloSystem::default()),
+            sizing: Sizing::Flat,
+            tournament: Sizing::AllIn,
+            caution: Caution::default(),
+            head_to_head: Blend {
+                min_meetings: 5,
+                weight: 0.5f32,
+            },
+        };
+        let winner = |decision| match decision {
+            Decision::Bet { winner, .. } => winner,
+            decision => panic!("unexpected decision: {:?}", decision),
+        };
+        // Four meetings aren't enough to go on.
+        assert_eq!(winner(strategy.decide(&one, &two, &context)), Winner::One);
+        // Five are: 0.64 * 0.5 + 1 / 7 * 0.5 < 0.5.
+        context.head_to_head.losses = 5;
+        assert_eq!(winner(strategy.decide(&one, &two, &context)), Winner::Two);
+    }
+
+    #[test]
+    fn test_favourite_skips_without_kelly_edge() {
+        let one = Player::new(String::from("one"));
+        let two = Player::new(String::from("two"));
+        let strategy = Favourite {
+            rating: Box::new(EloSystem::default()),
+            sizing: Sizing::Kelly {
+                fraction: 0.5f32,
+                floor: 100,
+                ceiling: None,
+            },
+            tournament: Sizing::AllIn,
+            caution: Caution::default(),
+            head_to_head: Blend::default(),
+        };
+        let context = Context {
+            balance: 10_000,
+            ..Default::default()
+        };
+        assert_eq!(
+            strategy.decide(&one, &two, &context),
+            Decision::Skip(String::from("nothing worth betting at 50.0%"))
+        );
+    }
+
+    #[test]
+    fn test_flat_minimum_wager() {
+        assert_eq!(Sizing::Flat.wager(0.9f32, None, 1_000), 420);
+    }
+
+    #[test]
+    fn test_kelly_scales_with_confidence() {
+        let sizing = Sizing::Kelly {
+            fraction: 1f32,
+            floor: 0,
+            ceiling: None,
+        };
+        let unsure = sizing.wager(0.51f32, None, 10_000);
+        let sure = sizing.wager(0.95f32, None, 10_000);
+        assert!(unsure < 250);
+        assert!(sure > 8_900);
+    }
+
+    #[test]
+    fn test_kelly_floor_and_ceiling() {
+        let sizing = Sizing::Kelly {
+            fraction: 0.5f32,
+            floor: 100,
+            ceiling: Some(1_000),
+        };
+        // No edge at these odds, so we don't bet at all.
+        assert_eq!(sizing.wager(0.5f32, Some(1f32), 10_000), 0);
+        assert_eq!(sizing.wager(0.4f32, Some(1f32), 10_000), 0);
+        // A small edge still bets the floor.
+        assert_eq!(sizing.wager(0.51f32, Some(1f32), 10_000), 100);
+        assert_eq!(sizing.wager(0.9f32, Some(1f32), 10_000), 1_000);
+        // Never more than we have.
+        assert_eq!(sizing.wager(0.9f32, None, 50), 50);
+    }
+
+    #[test]
+    fn test_from_config() {
+        let mut config = Config {
+            strategy: String::from("favourite"),
+            rating: String::from("glicko2"),
+            wager: String::from("kelly"),
+            tournament_wager: String::from("all-in"),
+            ..Default::default()
+        };
+        assert!(from_config(&config).is_some());
+        config.unsure_wager = String::from("nonsense");
+        assert!(from_config(&config).is_none());
+        config.unsure_wager = String::from("minimum");
+        config.strategy = String::from("nonsense");
+        assert!(from_config(&config).is_none());
+    }
+}
@@ -0,0 +1,189 @@
+use crate::elo::Winner;
+use crate::game::Tier;
+use crate::player::Player;
+use crate::rating::{Rating, RatingSystem};
+
+/// What goes between the members' names when a team is named after who's in it, e.g.
+/// "Ryu / Ken".
+const SEPARATOR: &str = " / ";
+
+/// Teams named by whoever requested the exhibition, e.g. "Team fathersonduo". There's no telling
+/// who's in them.
+const ANONYMOUS: &str = "team ";
+
+/// The characters fighting under `name`. That's just the one character for most matches, and
+/// nobody we know of for teams which don't say who's in them.
+pub fn members(name: &str) -> Vec<String> {
+    if name.contains(SEPARATOR) {
+        name.split(SEPARATOR)
+            .map(str::trim)
+            .filter(|member| !member.is_empty())
+            .map(String::from)
+            .collect()
+    } else if name
+        .get(..ANONYMOUS.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(ANONYMOUS))
+    {
+        Vec::new()
+    } else {
+        vec![String::from(name)]
+    }
+}
+
+/// Whether `name` is a team rather than a single character.
+pub fn is_team(name: &str) -> bool {
+    members(name) != [name]
+}
+
+/// The player a side fights as: for a team, the average of its members' ratings in each of the
+/// `systems`. A team we know nothing about is rated like a newcomer. A single character keeps
+/// their `Stats`, and a team takes those of its least experienced member, since it has none of
+/// its own.
+pub fn composite(
+    name: &str,
+    tier: Option<Tier>,
+    members: &[Player],
+    systems: &[Box<dyn RatingSystem>],
+) -> Player {
+    let mut team = Player::new(String::from(name));
+    team.tier = tier;
+    if members.is_empty() {
+        return team;
+    }
+    if let Some(member) = members.iter().min_by_key(|member| member.stats.matches) {
+        team.stats = member.stats.clone();
+    }
+
+    let count = members.len() as f64;
+    for system in systems {
+        let mut total = Rating::default();
+        for member in members {
+            let rating = member.rating(system.as_ref());
+            total.rating += rating.rating;
+            total.deviation += rating.deviation;
+            total.volatility += rating.volatility;
+            total.matches += rating.matches;
+        }
+        let average = Rating {
+            rating: total.rating / count,
+            deviation: total.deviation / count,
+            volatility: total.volatility / count,
+            matches: total.matches / members.len() as u32,
+        };
+        team.set_rating(system.as_ref(), average);
+    }
+    team
+}
+
+/// Update the members of both sides after a fight. Each member is rated as if they had fought the
+/// other side's composite on their own, which for a one on one fight is just a regular update.
+/// If we don't know who was on one of the sides nobody is updated, since we can't tell how
+/// strong they were.
+pub fn update(
+    systems: &[Box<dyn RatingSystem>],
+    winner: Winner,
+    one: &mut [Player],
+    two: &mut [Player],
+) {
+    if one.is_empty() || two.is_empty() {
+        return;
+    }
+
+    let one_team = composite("", None, one, systems);
+    let two_team = composite("", None, two, systems);
+    for member in one.iter_mut() {
+        let mut opponent = two_team.clone();
+        for system in systems {
+            system.update(winner, member, &mut opponent);
+        }
+    }
+    for member in two.iter_mut() {
+        let mut opponent = one_team.clone();
+        for system in systems {
+            system.update(winner, &mut opponent, member);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::elo::Elo;
+    use crate::rating::{self, EloSystem};
+
+    #[test]
+    fn test_members() {
+        assert_eq!(members("Ryu"), vec!["Ryu"]);
+        assert_eq!(members("Ryu / Ken"), vec!["Ryu", "Ken"]);
+        assert_eq!(members("Archer f/sn"), vec!["Archer f/sn"]);
+        assert!(members("Team fathersonduo").is_empty());
+        assert!(members("team rocket").is_empty());
+        assert!(is_team("Ryu / Ken"));
+        assert!(!is_team("Banjo & kazooie ex"));
+    }
+
+    #[test]
+    fn test_composite_averages_members() {
+        let systems = rating::all(&Config::default());
+        let mut ryu = Player::new(String::from("Ryu"));
+        ryu.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1200)));
+        let ken = Player::new(String::from("Ken"));
+
+        let team = composite("Ryu / Ken", None, &[ryu, ken], &systems);
+        assert_eq!(team.name, "Ryu / Ken");
+        assert_eq!(
+            team.rating(&EloSystem::default()),
+            Rating::from(Elo::with_rating(1100))
+        );
+    }
+
+    #[test]
+    fn test_composite_keeps_least_experienced_stats() {
+        let mut ryu = Player::new(String::from("Ryu"));
+        ryu.stats
+            .record(Winner::One, Winner::One, "2021-01-01 00:00:00");
+        let side = composite(
+            "Ryu",
+            None,
+            &[ryu.clone()],
+            &rating::all(&Config::default()),
+        );
+        assert_eq!(side.stats, ryu.stats);
+
+        let ken = Player::new(String::from("Ken"));
+        let side = composite(
+            "Ryu / Ken",
+            None,
+            &[ryu, ken],
+            &rating::all(&Config::default()),
+        );
+        assert_eq!(side.stats.matches, 0);
+    }
+
+    #[test]
+    fn test_update_one_on_one_is_a_regular_update() {
+        let systems = rating::all(&Config::default());
+        let mut one = Player::new(String::from("one"));
+        one.set_rating(&EloSystem::default(), Rating::from(Elo::with_rating(1200)));
+        let two = Player::new(String::from("two"));
+
+        let (mut expected_one, mut expected_two) = (one.clone(), two.clone());
+        for system in &systems {
+            system.update(Winner::Two, &mut expected_one, &mut expected_two);
+        }
+
+        let (mut one, mut two) = (vec![one], vec![two]);
+        update(&systems, Winner::Two, &mut one, &mut two);
+        assert_eq!(one[0].ratings, expected_one.ratings);
+        assert_eq!(two[0].ratings, expected_two.ratings);
+    }
+
+    #[test]
+    fn test_update_skips_unknown_teams() {
+        let systems = rating::all(&Config::default());
+        let mut one = vec![Player::new(String::from("one"))];
+        update(&systems, Winner::One, &mut one, &mut []);
+        assert!(one[0].ratings.is_empty());
+    }
+}